@@ -18,19 +18,40 @@ macro_rules! json_sources {
     };
 }
 
+macro_rules! plaintext_v6_sources {
+    () => {
+        include!("includes/plaintext_v6_sources")
+    };
+}
+
+macro_rules! json_v6_sources {
+    () => {
+        include!("includes/json_v6_sources")
+    };
+}
+
 async fn make_default_sources_toml() -> io::Result<()> {
     let mut data = String::new();
 
-    let plain_sources = plaintext_sources!();
-    for source in plain_sources {
+    let plain_sources = plaintext_sources!()
+        .map(|source| ("v4", source))
+        .into_iter()
+        .chain(plaintext_v6_sources!().map(|source| ("v6", source)));
+    for (family, source) in plain_sources {
         writeln!(data, r#"["{source}"]"#).unwrap();
+        writeln!(data, r#"family = "{family}""#).unwrap();
         writeln!(data, "steps = [\"Plaintext\"]\n").unwrap();
     }
 
-    let plain_sources = json_sources!();
-    for (source, key) in plain_sources {
+    let json_sources = json_sources!()
+        .map(|source| ("v4", source))
+        .into_iter()
+        .chain(json_v6_sources!().map(|source| ("v6", source)));
+    for (family, (source, key)) in json_sources {
         writeln!(data, r#"["{source}"]"#).unwrap();
+        writeln!(data, r#"family = "{family}""#).unwrap();
         writeln!(data, r#"steps = [{{ Json = {{ key = "{key}" }} }}]"#).unwrap();
+        writeln!(data).unwrap();
     }
 
     tokio::fs::write("includes/sources.toml", data.trim()).await
@@ -70,17 +91,20 @@ async fn make_default_sources_rs() -> io::Result<()> {
         };
     }
 
-    let mut sources = plaintext_sources!().map(|url| (url, vec![])).to_vec();
-
-    sources.extend(json_sources!().map(|(source, key)| {
-        (
-            source,
-            vec![format!(
-                r#"ProcessStep::Json {{ key: "{}".into() }}"#,
-                key.escape_debug()
-            )],
-        )
-    }));
+    let json_step = |key: &str| {
+        vec![format!(
+            r#"ProcessStep::Json {{ key: "{}".into() }}"#,
+            key.escape_debug()
+        )]
+    };
+
+    let v4 = || format!("IpFamily::V4");
+    let v6 = || format!("IpFamily::V6");
+
+    let mut sources = plaintext_sources!().map(|url| (url, v4(), vec![])).to_vec();
+    sources.extend(plaintext_v6_sources!().map(|url| (url, v6(), vec![])));
+    sources.extend(json_sources!().map(|(source, key)| (source, v4(), json_step(key))));
+    sources.extend(json_v6_sources!().map(|(source, key)| (source, v6(), json_step(key))));
 
     file.write_all(format!("{sources:?}").0.as_bytes()).await?;
 
@@ -0,0 +1,116 @@
+use crate::config::api_fields::Zone;
+use crate::config::family::IpFamily;
+use crate::DdnsContext;
+use anyhow::{Context, Result};
+use hickory_resolver::config::{NameServerConfigGroup, ResolverConfig, ResolverOpts};
+use hickory_resolver::proto::rr::RecordType;
+use hickory_resolver::TokioAsyncResolver;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+const QUERY_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// parses a configured nameserver, a hostname is resolved through the system resolver
+async fn nameserver_addrs(nameserver: &str) -> Result<Vec<SocketAddr>> {
+    if let Ok(addr) = nameserver.parse::<SocketAddr>() {
+        return Ok(vec![addr]);
+    }
+    if let Ok(ip) = nameserver.parse::<IpAddr>() {
+        return Ok(vec![SocketAddr::new(ip, 53)]);
+    }
+
+    let addrs = tokio::net::lookup_host((nameserver, 53))
+        .await
+        .with_context(|| format!("unable to resolve the nameserver {nameserver}"))?
+        .collect::<Vec<_>>();
+    anyhow::ensure!(
+        !addrs.is_empty(),
+        "the nameserver {nameserver} has no addresses"
+    );
+    Ok(addrs)
+}
+
+/// asks a single nameserver, without any caching, what it serves for the record
+pub(crate) async fn query(
+    nameserver: SocketAddr,
+    name: &str,
+    family: IpFamily,
+) -> Result<Vec<IpAddr>> {
+    let group = NameServerConfigGroup::from_ips_clear(&[nameserver.ip()], nameserver.port(), true);
+    let mut opts = ResolverOpts::default();
+    opts.cache_size = 0;
+    opts.attempts = 1;
+    opts.timeout = QUERY_TIMEOUT;
+    opts.use_hosts_file = false;
+    opts.recursion_desired = false;
+
+    let resolver = TokioAsyncResolver::tokio(ResolverConfig::from_parts(None, vec![], group), opts);
+    let record_type = match family {
+        IpFamily::V4 => RecordType::A,
+        IpFamily::V6 => RecordType::AAAA,
+    };
+
+    let lookup = resolver.lookup(format!("{name}."), record_type).await?;
+    Ok(lookup.iter().filter_map(|data| data.ip_addr()).collect())
+}
+
+/// asks every nameserver, and counts how many of them serve the address
+pub(crate) async fn serving(
+    nameservers: &[SocketAddr],
+    name: &str,
+    family: IpFamily,
+    ip: IpAddr,
+) -> usize {
+    let answers = futures::future::join_all(
+        nameservers
+            .iter()
+            .map(|&nameserver| query(nameserver, name, family)),
+    )
+    .await;
+
+    answers
+        .iter()
+        .filter(|answer| answer.as_ref().is_ok_and(|ips| ips.contains(&ip)))
+        .count()
+}
+
+impl DdnsContext {
+    /// the nameservers to ask about the zone, the configured ones,
+    /// or the ones cloudflare assigned to the zone, which are cached
+    pub(crate) async fn nameservers(
+        &self,
+        zone: &Zone,
+        configured: &[Box<str>],
+    ) -> Result<Arc<[SocketAddr]>> {
+        let zone_id = self.zone_id(zone).await?;
+        if configured.is_empty() {
+            if let Some(addrs) = self.nameservers.lock().unwrap().get(&zone_id) {
+                return Ok(Arc::clone(addrs));
+            }
+        }
+
+        let names = match configured {
+            [] => {
+                let info = self.cloudflare.get_zone(zone.account(), &zone_id).await?;
+                info.name_servers
+            }
+            nameservers => nameservers.to_vec(),
+        };
+
+        anyhow::ensure!(!names.is_empty(), "there are no nameservers to ask");
+        let addrs = futures::future::try_join_all(names.iter().map(|name| nameserver_addrs(name)))
+            .await?
+            .into_iter()
+            .flatten()
+            .collect::<Arc<[_]>>();
+
+        if configured.is_empty() {
+            self.nameservers
+                .lock()
+                .unwrap()
+                .insert(zone_id, Arc::clone(&addrs));
+        }
+        Ok(addrs)
+    }
+}
@@ -0,0 +1,184 @@
+use reqwest::StatusCode;
+use serde::Deserialize;
+use std::fmt::{Display, Formatter};
+use thiserror::Error;
+
+/// a single entry of the `errors[]` or `messages[]` arrays cloudflare responds with
+#[derive(Debug, Clone, Deserialize)]
+pub struct ApiMessage {
+    #[serde(default)]
+    pub code: u32,
+    pub message: Box<str>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ApiMessages(pub Box<[ApiMessage]>);
+
+impl ApiMessages {
+    fn has_code(&self, code: u32) -> bool {
+        self.0.iter().any(|msg| msg.code == code)
+    }
+
+    fn has_any_code(&self, codes: &[u32]) -> bool {
+        codes.iter().any(|&code| self.has_code(code))
+    }
+}
+
+impl Display for ApiMessages {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.0.is_empty() {
+            return f.write_str("no details given");
+        }
+
+        for (i, ApiMessage { code, message }) in self.0.iter().enumerate() {
+            if i != 0 {
+                f.write_str("; ")?;
+            }
+            write!(f, "[{code}] {message}")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ApiError {
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+    #[error(
+        "cloudflare rejected the credentials, check the api token/key and its permissions: {0}"
+    )]
+    Auth(ApiMessages),
+    #[error("cloudflare could not find the requested resource: {0}")]
+    NotFound(ApiMessages),
+    #[error("cloudflare rejected the request as invalid: {0}")]
+    Validation(ApiMessages),
+    #[error("cloudflare is rate limiting this token: {0}")]
+    RateLimited(ApiMessages),
+    #[error("cloudflare responded with {status}: {errors}")]
+    Api {
+        status: StatusCode,
+        errors: ApiMessages,
+    },
+    #[error("unable to serialize the request: {0}")]
+    Serialize(#[from] serde_json::Error),
+    #[error("cloudflare sent an unexpected response ({status}): {reason}")]
+    InvalidResponse {
+        status: StatusCode,
+        reason: Box<str>,
+    },
+}
+
+impl ApiError {
+    // https://developers.cloudflare.com/fundamentals/api/troubleshooting/
+    const AUTH_CODES: &'static [u32] = &[6003, 9103, 9106, 9107, 9109, 10000, 10001];
+    const NOT_FOUND_CODES: &'static [u32] = &[7003, 81044];
+    const RATE_LIMIT_CODES: &'static [u32] = &[971, 10100];
+
+    /// errors that might go away by themselves, as opposed to ones caused by the config or credentials
+    pub fn is_transient(&self) -> bool {
+        match self {
+            ApiError::Http(_) | ApiError::RateLimited(_) | ApiError::InvalidResponse { .. } => true,
+            ApiError::Api { status, .. } => status.is_server_error(),
+            ApiError::Auth(_)
+            | ApiError::NotFound(_)
+            | ApiError::Validation(_)
+            | ApiError::Serialize(_) => false,
+        }
+    }
+
+    pub(super) fn from_response(status: StatusCode, errors: ApiMessages) -> Self {
+        if errors.has_any_code(Self::AUTH_CODES) {
+            return ApiError::Auth(errors);
+        }
+        if errors.has_any_code(Self::NOT_FOUND_CODES) {
+            return ApiError::NotFound(errors);
+        }
+        if errors.has_any_code(Self::RATE_LIMIT_CODES) {
+            return ApiError::RateLimited(errors);
+        }
+
+        match status {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => ApiError::Auth(errors),
+            StatusCode::NOT_FOUND => ApiError::NotFound(errors),
+            StatusCode::TOO_MANY_REQUESTS => ApiError::RateLimited(errors),
+            StatusCode::BAD_REQUEST | StatusCode::CONFLICT | StatusCode::UNPROCESSABLE_ENTITY => {
+                ApiError::Validation(errors)
+            }
+            status => ApiError::Api { status, errors },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(codes: &[u32]) -> ApiMessages {
+        ApiMessages(
+            codes
+                .iter()
+                .map(|&code| ApiMessage {
+                    code,
+                    message: Box::from("message"),
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn codes_win_over_the_status() {
+        let err = ApiError::from_response(StatusCode::BAD_REQUEST, messages(&[1000, 9109]));
+        assert!(matches!(err, ApiError::Auth(_)));
+
+        let err = ApiError::from_response(StatusCode::BAD_REQUEST, messages(&[81044]));
+        assert!(matches!(err, ApiError::NotFound(_)));
+
+        let err = ApiError::from_response(StatusCode::OK, messages(&[10100]));
+        assert!(matches!(err, ApiError::RateLimited(_)));
+    }
+
+    #[test]
+    fn falls_back_to_the_status() {
+        let classify = |status| ApiError::from_response(status, messages(&[1004]));
+
+        assert!(matches!(classify(StatusCode::FORBIDDEN), ApiError::Auth(_)));
+        assert!(matches!(
+            classify(StatusCode::NOT_FOUND),
+            ApiError::NotFound(_)
+        ));
+        assert!(matches!(
+            classify(StatusCode::TOO_MANY_REQUESTS),
+            ApiError::RateLimited(_)
+        ));
+        assert!(matches!(
+            classify(StatusCode::UNPROCESSABLE_ENTITY),
+            ApiError::Validation(_)
+        ));
+        assert!(matches!(
+            classify(StatusCode::BAD_GATEWAY),
+            ApiError::Api {
+                status: StatusCode::BAD_GATEWAY,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn only_server_errors_are_transient() {
+        let transient = |status| ApiError::from_response(status, messages(&[])).is_transient();
+
+        assert!(transient(StatusCode::SERVICE_UNAVAILABLE));
+        assert!(transient(StatusCode::TOO_MANY_REQUESTS));
+        assert!(!transient(StatusCode::UNAUTHORIZED));
+        assert!(!transient(StatusCode::BAD_REQUEST));
+    }
+
+    #[test]
+    fn lists_every_message() {
+        let mut errors = messages(&[1004, 9109]);
+        errors.0[1].message = Box::from("bad token");
+        assert_eq!(errors.to_string(), "[1004] message; [9109] bad token");
+        assert_eq!(ApiMessages::default().to_string(), "no details given");
+    }
+}
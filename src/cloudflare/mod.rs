@@ -0,0 +1,341 @@
+use crate::config::api_fields::Account;
+use crate::dbg_println;
+use crate::retrying_client::{RequestBuilder, RetryingClient};
+use reqwest::Method;
+use serde::de::{DeserializeOwned, IgnoredAny};
+use serde::Deserialize;
+use url::Url;
+
+mod error;
+mod types;
+
+pub use error::{ApiError, ApiMessage, ApiMessages};
+use types::ResultInfo;
+pub use types::{
+    BatchBody, BatchDelete, BatchPatch, BatchResult, BulkOperation, BulkOperationId, DnsRecord,
+    ListInfo, ListItem, NewListItem, RecordBody, RecordFilter, SvcbData, SvcbRecord, TokenStatus,
+    ZoneInfo,
+};
+
+pub const API_BASE: &str = "https://api.cloudflare.com/client/v4";
+
+const ZONES_PER_PAGE: &str = "50";
+const RECORDS_PER_PAGE: &str = "5000";
+
+/// the `success`/`errors[]`/`messages[]` envelope every cloudflare response is wrapped in
+#[derive(Debug, Deserialize)]
+struct Envelope {
+    #[serde(default)]
+    success: bool,
+    #[serde(default)]
+    errors: Vec<ApiMessage>,
+    #[serde(default)]
+    messages: Vec<ApiMessage>,
+    #[serde(default)]
+    result: serde_json::Value,
+    #[serde(default)]
+    result_info: Option<ResultInfo>,
+}
+
+/// A typed client for the parts of the cloudflare v4 api we use
+#[derive(Clone)]
+pub struct Client {
+    client: RetryingClient,
+    base: Url,
+}
+
+impl Client {
+    pub fn new(client: RetryingClient, base: Url) -> Self {
+        Client { client, base }
+    }
+
+    fn endpoint<'a>(&self, segments: impl IntoIterator<Item = &'a str>) -> Url {
+        let mut url = self.base.clone();
+        url.path_segments_mut()
+            .expect("the api base is checked to be a valid base url")
+            .pop_if_empty()
+            .extend(segments);
+        url
+    }
+
+    async fn send<T: DeserializeOwned>(
+        &self,
+        request: RequestBuilder,
+    ) -> Result<(T, Option<ResultInfo>), ApiError> {
+        let response = request.send().await?;
+        let status = response.status();
+        let bytes = response.bytes().await?;
+
+        let envelope = serde_json::from_slice::<Envelope>(&bytes).map_err(|err| {
+            match status.is_success() {
+                true => ApiError::InvalidResponse {
+                    status,
+                    reason: err.to_string().into_boxed_str(),
+                },
+                // error pages from proxies in between aren't json
+                false => ApiError::from_response(status, ApiMessages::default()),
+            }
+        })?;
+
+        for ApiMessage { code, message } in &envelope.messages {
+            dbg_println!("cloudflare: [{code}] {message}");
+        }
+
+        if !status.is_success() || !envelope.success {
+            return Err(ApiError::from_response(
+                status,
+                ApiMessages(envelope.errors.into_boxed_slice()),
+            ));
+        }
+
+        let result = T::deserialize(envelope.result).map_err(|err| ApiError::InvalidResponse {
+            status,
+            reason: err.to_string().into_boxed_str(),
+        })?;
+
+        Ok((result, envelope.result_info))
+    }
+
+    async fn request<T: DeserializeOwned>(
+        &self,
+        account: &Account,
+        method: Method,
+        url: Url,
+        body: Option<&impl serde::Serialize>,
+    ) -> Result<T, ApiError> {
+        let mut request = account.authorize(self.client.request(method, url));
+        if let Some(body) = body {
+            request = request.json(serde_json::to_vec(body)?);
+        }
+
+        self.send(request).await.map(|(result, _)| result)
+    }
+
+    /// fetches every page of a list endpoint
+    async fn list<T: DeserializeOwned>(
+        &self,
+        account: &Account,
+        url: Url,
+        per_page: &str,
+    ) -> Result<Vec<T>, ApiError> {
+        let mut items = vec![];
+        let mut page = 1_u32;
+        loop {
+            let mut url = url.clone();
+            url.query_pairs_mut()
+                .append_pair("page", &page.to_string())
+                .append_pair("per_page", per_page);
+
+            let (result, info) = self
+                .send::<Vec<T>>(account.authorize(self.client.get(url)))
+                .await?;
+            items.extend(result);
+
+            match info {
+                Some(info) if info.page < info.total_pages => page = info.page + 1,
+                _ => break,
+            }
+        }
+
+        Ok(items)
+    }
+
+    /// fetches every item of an endpoint that pages through cursors
+    async fn list_cursor<T: DeserializeOwned>(
+        &self,
+        account: &Account,
+        url: Url,
+    ) -> Result<Vec<T>, ApiError> {
+        let mut items = vec![];
+        let mut cursor = None::<Box<str>>;
+        loop {
+            let mut url = url.clone();
+            if let Some(cursor) = &cursor {
+                url.query_pairs_mut().append_pair("cursor", cursor);
+            }
+
+            let (result, info) = self
+                .send::<Vec<T>>(account.authorize(self.client.get(url)))
+                .await?;
+            items.extend(result);
+
+            cursor = info.and_then(|info| info.cursors?.after);
+            if cursor.is_none() {
+                break;
+            }
+        }
+
+        Ok(items)
+    }
+
+    /// lists the zones visible to the account, optionally only the one with this exact name
+    pub async fn list_zones(
+        &self,
+        account: &Account,
+        name: Option<&str>,
+    ) -> Result<Vec<ZoneInfo>, ApiError> {
+        let mut url = self.endpoint(["zones"]);
+        if let Some(name) = name {
+            url.query_pairs_mut().append_pair("name", name);
+        }
+
+        self.list(account, url, ZONES_PER_PAGE).await
+    }
+
+    pub async fn get_zone(&self, account: &Account, zone_id: &str) -> Result<ZoneInfo, ApiError> {
+        let url = self.endpoint(["zones", zone_id]);
+        self.request(account, Method::GET, url, None::<&()>).await
+    }
+
+    /// checks that the account's api token is valid and active
+    pub async fn verify_token(&self, account: &Account) -> Result<TokenStatus, ApiError> {
+        let url = self.endpoint(["user", "tokens", "verify"]);
+        self.request(account, Method::GET, url, None::<&()>).await
+    }
+
+    /// lists the records matching the filter, as a [`DnsRecord`] or any other shape of record
+    pub async fn list_dns_records<T: DeserializeOwned>(
+        &self,
+        account: &Account,
+        zone_id: &str,
+        filter: RecordFilter<'_>,
+    ) -> Result<Vec<T>, ApiError> {
+        let mut url = self.endpoint(["zones", zone_id, "dns_records"]);
+        {
+            let mut query = url.query_pairs_mut();
+            if let Some(record_type) = filter.record_type {
+                query.append_pair("type", record_type);
+            }
+            if let Some(name) = filter.name {
+                query.append_pair("name", name);
+            }
+        }
+
+        self.list(account, url, RECORDS_PER_PAGE).await
+    }
+
+    pub async fn get_dns_record<T: DeserializeOwned>(
+        &self,
+        account: &Account,
+        zone_id: &str,
+        record_id: &str,
+    ) -> Result<T, ApiError> {
+        let url = self.endpoint(["zones", zone_id, "dns_records", record_id]);
+        self.request(account, Method::GET, url, None::<&()>).await
+    }
+
+    pub async fn create_dns_record(
+        &self,
+        account: &Account,
+        zone_id: &str,
+        body: &RecordBody<'_>,
+    ) -> Result<DnsRecord, ApiError> {
+        let url = self.endpoint(["zones", zone_id, "dns_records"]);
+        self.request(account, Method::POST, url, Some(body)).await
+    }
+
+    pub async fn patch_dns_record(
+        &self,
+        account: &Account,
+        zone_id: &str,
+        record_id: &str,
+        body: &RecordBody<'_>,
+    ) -> Result<DnsRecord, ApiError> {
+        let url = self.endpoint(["zones", zone_id, "dns_records", record_id]);
+        self.request(account, Method::PATCH, url, Some(body)).await
+    }
+
+    /// applies several record changes to a zone in a single atomic request
+    pub async fn batch_dns_records(
+        &self,
+        account: &Account,
+        zone_id: &str,
+        body: &BatchBody<'_>,
+    ) -> Result<BatchResult, ApiError> {
+        let url = self.endpoint(["zones", zone_id, "dns_records", "batch"]);
+        self.request(account, Method::POST, url, Some(body)).await
+    }
+
+    pub async fn list_lists(
+        &self,
+        account: &Account,
+        account_id: &str,
+    ) -> Result<Vec<ListInfo>, ApiError> {
+        let url = self.endpoint(["accounts", account_id, "rules", "lists"]);
+        self.request(account, Method::GET, url, None::<&()>).await
+    }
+
+    pub async fn list_list_items(
+        &self,
+        account: &Account,
+        account_id: &str,
+        list_id: &str,
+    ) -> Result<Vec<ListItem>, ApiError> {
+        let url = self.endpoint(["accounts", account_id, "rules", "lists", list_id, "items"]);
+        self.list_cursor(account, url).await
+    }
+
+    /// appends items to a list, leaving the existing items alone
+    pub async fn add_list_items(
+        &self,
+        account: &Account,
+        account_id: &str,
+        list_id: &str,
+        items: &[NewListItem<'_>],
+    ) -> Result<BulkOperationId, ApiError> {
+        let url = self.endpoint(["accounts", account_id, "rules", "lists", list_id, "items"]);
+        self.request(account, Method::POST, url, Some(&items)).await
+    }
+
+    pub async fn delete_list_items(
+        &self,
+        account: &Account,
+        account_id: &str,
+        list_id: &str,
+        item_ids: &[&str],
+    ) -> Result<BulkOperationId, ApiError> {
+        #[derive(serde::Serialize)]
+        struct ItemId<'a> {
+            id: &'a str,
+        }
+
+        let items = item_ids.iter().map(|&id| ItemId { id }).collect::<Vec<_>>();
+        let url = self.endpoint(["accounts", account_id, "rules", "lists", list_id, "items"]);
+        self.request(
+            account,
+            Method::DELETE,
+            url,
+            Some(&serde_json::json!({ "items": items })),
+        )
+        .await
+    }
+
+    pub async fn get_bulk_operation(
+        &self,
+        account: &Account,
+        account_id: &str,
+        operation_id: &str,
+    ) -> Result<BulkOperation, ApiError> {
+        let url = self.endpoint([
+            "accounts",
+            account_id,
+            "rules",
+            "lists",
+            "bulk_operations",
+            operation_id,
+        ]);
+        self.request(account, Method::GET, url, None::<&()>).await
+    }
+
+    pub async fn delete_dns_record(
+        &self,
+        account: &Account,
+        zone_id: &str,
+        record_id: &str,
+    ) -> Result<(), ApiError> {
+        let url = self.endpoint(["zones", zone_id, "dns_records", record_id]);
+        self.request::<IgnoredAny>(account, Method::DELETE, url, None::<&()>)
+            .await
+            .map(drop)
+    }
+}
@@ -0,0 +1,183 @@
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ZoneInfo {
+    pub id: Box<str>,
+    pub name: Box<str>,
+    /// what the credentials may do in this zone, like `#dns_records:edit`,
+    /// cloudflare doesn't always fill this in
+    #[serde(default)]
+    pub permissions: Vec<Box<str>>,
+    /// the authoritative nameservers cloudflare assigned to the zone
+    #[serde(default)]
+    pub name_servers: Vec<Box<str>>,
+}
+
+/// the state of an api token, as reported by `user/tokens/verify`
+#[derive(Debug, Clone, Deserialize)]
+pub struct TokenStatus {
+    pub status: Box<str>,
+    #[serde(default)]
+    pub expires_on: Option<Box<str>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DnsRecord {
+    pub id: Box<str>,
+    pub name: Box<str>,
+    #[serde(rename = "type")]
+    pub record_type: Box<str>,
+    pub content: Box<str>,
+    #[serde(default)]
+    pub proxied: bool,
+    pub ttl: u32,
+    #[serde(default)]
+    pub comment: Option<Box<str>>,
+    #[serde(default)]
+    pub tags: Vec<Box<str>>,
+    /// only set on MX records
+    #[serde(default)]
+    pub priority: Option<u16>,
+    /// when the record was created, like `2014-01-01T05:20:00.12345Z`
+    #[serde(default)]
+    pub created_on: Option<Box<str>>,
+}
+
+/// the structured data of an HTTPS or SVCB record
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct SvcbData {
+    pub priority: u16,
+    pub target: Box<str>,
+    /// the SvcParams, like `alpn="h3,h2" ipv4hint="192.0.2.1"`
+    #[serde(default)]
+    pub value: Box<str>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SvcbRecord {
+    pub id: Box<str>,
+    pub name: Box<str>,
+    #[serde(rename = "type")]
+    pub record_type: Box<str>,
+    pub data: SvcbData,
+}
+
+/// the fields sent when creating or patching a record,
+/// fields left as `None` are not sent so cloudflare keeps their current value
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RecordBody<'a> {
+    #[serde(rename = "type")]
+    pub record_type: &'a str,
+    pub name: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<Cow<'a, str>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxied: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tags: Option<&'a [Box<str>]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub priority: Option<u16>,
+    /// the structured data of the record types that don't have plain content
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<&'a SvcbData>,
+}
+
+/// a record patch inside a batch, identified by its id
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchPatch<'a> {
+    pub id: &'a str,
+    #[serde(flatten)]
+    pub body: RecordBody<'a>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchDelete<'a> {
+    pub id: &'a str,
+}
+
+/// several record changes to a single zone, cloudflare applies them all or none of them
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BatchBody<'a> {
+    pub deletes: Vec<BatchDelete<'a>>,
+    pub patches: Vec<BatchPatch<'a>>,
+    pub posts: Vec<RecordBody<'a>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BatchResult {
+    #[serde(default)]
+    pub deletes: Vec<IgnoredAny>,
+    #[serde(default)]
+    pub patches: Vec<DnsRecord>,
+    #[serde(default)]
+    pub posts: Vec<DnsRecord>,
+}
+
+/// filters for listing dns records, `None` matches anything
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RecordFilter<'a> {
+    pub record_type: Option<&'a str>,
+    pub name: Option<&'a str>,
+}
+
+/// an account level list, only the `ip` kind is of interest to us
+#[derive(Debug, Clone, Deserialize)]
+pub struct ListInfo {
+    pub id: Box<str>,
+    pub name: Box<str>,
+    pub kind: Box<str>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ListItem {
+    pub id: Box<str>,
+    /// an address or a cidr range
+    #[serde(default)]
+    pub ip: Option<Box<str>>,
+    #[serde(default)]
+    pub comment: Option<Box<str>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NewListItem<'a> {
+    pub ip: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<&'a str>,
+}
+
+/// list changes are applied in the background, and tracked through an operation
+#[derive(Debug, Clone, Deserialize)]
+pub struct BulkOperationId {
+    pub operation_id: Box<str>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BulkOperation {
+    /// `pending`, `running`, `completed` or `failed`
+    pub status: Box<str>,
+    #[serde(default)]
+    pub error: Option<Box<str>>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub(super) struct Cursors {
+    #[serde(default)]
+    pub after: Option<Box<str>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub(super) struct ResultInfo {
+    #[serde(default)]
+    pub page: u32,
+    #[serde(default)]
+    pub total_pages: u32,
+    /// set instead of pages by the endpoints that use cursor pagination
+    #[serde(default)]
+    pub cursors: Option<Cursors>,
+}
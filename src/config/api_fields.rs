@@ -1,8 +1,23 @@
+use crate::cloudflare::API_BASE;
+use crate::config::family::{Families, IpFamily};
+use crate::config::template;
+use crate::config::time::Time;
 use crate::config::Deserializable;
+use crate::retrying_client::{RequestBuilder, AUTHORIZATION_EMAIL, AUTHORIZATION_KEY};
+use crate::util;
 use anyhow::Result;
 use reqwest::header::HeaderValue;
-use serde::de::Error;
+use reqwest::header::AUTHORIZATION;
+use serde::de::value::{self, MapAccessDeserializer, SeqAccessDeserializer};
+use serde::de::{Error, MapAccess, SeqAccess, Visitor};
 use serde::{Deserialize, Deserializer};
+use std::fmt::Formatter;
+use std::marker::PhantomData;
+use std::net::{IpAddr, Ipv6Addr};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use url::Url;
 
 #[derive(Eq, Ord, PartialOrd, PartialEq, Debug)]
 pub(super) enum Auth {
@@ -12,39 +27,97 @@ pub(super) enum Auth {
 
 #[derive(Eq, Ord, PartialOrd, PartialEq, Debug)]
 pub struct Account {
-    pub(super) email: HeaderValue,
+    /// only needed alongside a global api key, tokens identify the user by themselves
+    pub(super) email: Option<HeaderValue>,
     pub(super) auth: Auth,
 }
 
+impl Account {
+    /// an account authenticated by an api token, like `api-token` in `[account]`
+    pub fn token(token: &str) -> Result<Account> {
+        let inner = AccountInner {
+            email: None,
+            auth_token: Some(Box::from(token)),
+            auth_key: None,
+        };
+        Ok(inner.into_account::<value::Error>()?)
+    }
+
+    /// an account authenticated by the global api key, which only works along with the email
+    pub fn global_key(email: &str, key: &str) -> Result<Account> {
+        let inner = AccountInner {
+            email: Some(Box::from(email)),
+            auth_token: None,
+            auth_key: Some(Box::from(key)),
+        };
+        Ok(inner.into_account::<value::Error>()?)
+    }
+
+    pub fn uses_token(&self) -> bool {
+        matches!(self.auth, Auth::Token(_))
+    }
+
+    /// tells accounts apart in the state files without writing the credentials down,
+    /// FNV-1a so it stays the same across builds
+    pub(crate) fn fingerprint(&self) -> u64 {
+        let (kind, auth): (&[u8], _) = match &self.auth {
+            Auth::Token(token) => (b"token", token),
+            Auth::Key(key) => (b"key", key),
+        };
+        let email = self.email.as_ref().map_or(&b""[..], HeaderValue::as_bytes);
+
+        [email, b"\0", kind, b"\0", auth.as_bytes()]
+            .into_iter()
+            .flatten()
+            .fold(0xcbf29ce484222325, |hash, &byte| {
+                (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+            })
+    }
+
+    pub(crate) fn authorize(&self, mut request: RequestBuilder) -> RequestBuilder {
+        if let Some(email) = &self.email {
+            request = request.header(AUTHORIZATION_EMAIL, email.clone());
+        }
+
+        match &self.auth {
+            Auth::Token(token_header) => request.header(AUTHORIZATION, token_header.clone()),
+            Auth::Key(key_header) => request.header(AUTHORIZATION_KEY, key_header.clone()),
+        }
+    }
+}
+
 macro_rules! invalid_header {
     ($field:literal) => {
         Error::custom(concat!($field, " can't be parsed as a valid http header"))
     };
 }
 
-impl<'de> Deserialize<'de> for Account {
-    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
-    where
-        D: Deserializer<'de>,
-    {
-        #[derive(Deserialize)]
-        struct AccountInner {
-            email: Box<str>,
-            #[serde(alias = "api-token")]
-            auth_token: Option<Box<str>>,
-            #[serde(alias = "auth-key")]
-            auth_key: Option<Box<str>>,
-        }
-        let inner = AccountInner::deserialize(deserializer)?;
+#[derive(Deserialize)]
+struct AccountInner {
+    #[serde(default)]
+    email: Option<Box<str>>,
+    #[serde(alias = "api-token")]
+    auth_token: Option<Box<str>>,
+    #[serde(alias = "auth-key")]
+    auth_key: Option<Box<str>>,
+}
 
-        let email = HeaderValue::from_str(&inner.email).map_err(|_| invalid_header!("email"))?;
+impl AccountInner {
+    fn into_account<E: Error>(self) -> Result<Account, E> {
+        let email = self
+            .email
+            .map(|email| HeaderValue::from_str(&email).map_err(|_| invalid_header!("email")))
+            .transpose()?;
 
-        let auth = match (inner.auth_token, inner.auth_key) {
+        let auth = match (self.auth_token, self.auth_key) {
             (Some(token), None) => Auth::Token(
                 HeaderValue::from_str(&("Bearer ".to_owned() + &token))
                     .map_err(|_| invalid_header!("auth-token"))?,
             ),
             (None, Some(key)) => {
+                if email.is_none() {
+                    return Err(Error::custom("an email is required when using an auth-key"));
+                }
                 Auth::Key(HeaderValue::from_str(&key).map_err(|_| invalid_header!("auth-key"))?)
             }
             (None, None) => return Err(Error::missing_field("auth-token")),
@@ -55,47 +128,738 @@ impl<'de> Deserialize<'de> for Account {
     }
 }
 
-#[derive(Eq, Ord, PartialOrd, PartialEq, Debug)]
-pub struct Zone {
-    id: Box<str>,
-    record: Box<str>,
-    proxied: bool,
+impl<'de> Deserialize<'de> for Account {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        AccountInner::deserialize(deserializer)?.into_account()
+    }
+}
+
+/// how to find our own entry inside a record set that holds several values
+#[derive(Eq, Ord, PartialOrd, PartialEq, Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RoundRobin {
+    /// the entry that holds the last address we published
+    LastIp,
+    /// the entry with this exact comment
+    Comment(Box<str>),
+    /// the entry carrying this tag, in the `name:value` format
+    Tag(Box<str>),
+}
+
+/// what happens to a record once the address of its family can't be resolved anymore
+#[derive(Eq, Ord, PartialOrd, PartialEq, Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OnMissing {
+    /// leave the last published address in place
+    #[default]
+    Keep,
+    /// delete our record, it's created again once the address is back
+    Delete,
+    /// point the record at a fallback address, one for each family
+    Placeholder(Box<[IpAddr]>),
+}
+
+/// where the delegated ipv6 prefix of a lan host is taken from
+#[derive(Eq, Ord, PartialOrd, PartialEq, Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PrefixSource {
+    /// the public ipv6 address the ip sources resolve
+    #[default]
+    IpSource,
+    /// a global address on this local interface
+    Iface(Box<str>),
+}
+
+/// a host behind the router, whose AAAA record is the current prefix followed by its own suffix
+#[derive(Eq, Ord, PartialOrd, PartialEq, Debug, Clone)]
+pub struct LanHost {
+    suffix: Ipv6Addr,
+    prefix_length: u8,
+    prefix_from: PrefixSource,
+}
+
+impl LanHost {
+    const DEFAULT_PREFIX_LENGTH: u8 = 64;
+
+    /// the interface identifier, along with any subnet bits past the prefix
+    pub fn suffix(&self) -> Ipv6Addr {
+        self.suffix
+    }
+
+    pub fn prefix_length(&self) -> u8 {
+        self.prefix_length
+    }
+
+    pub fn prefix_from(&self) -> &PrefixSource {
+        &self.prefix_from
+    }
+}
+
+/// what happens when someone else changed a record's address since we last wrote it
+#[derive(Eq, Ord, PartialOrd, PartialEq, Debug, Copy, Clone, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutsideChanges {
+    /// overwrite it without a word
+    Overwrite,
+    /// overwrite it with a warning
+    #[default]
+    Warn,
+    /// warn, and leave it alone until the change is acknowledged
+    Refuse,
 }
 
-impl<'de> Deserialize<'de> for Zone {
+/// one of the addresses a failover record can point at
+#[derive(Eq, Ord, PartialOrd, PartialEq, Debug, Copy, Clone)]
+pub enum Candidate {
+    /// the address the record would get without failover
+    Dynamic,
+    /// a fixed backup address
+    Static(IpAddr),
+}
+
+impl<'de> Deserialize<'de> for Candidate {
     fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
     where
         D: Deserializer<'de>,
     {
-        #[derive(Deserialize)]
-        struct ZoneInner {
-            id: Box<str>,
-            record: String,
+        let candidate = <Box<str>>::deserialize(deserializer)?;
+        match &*candidate {
+            "dynamic" => Ok(Candidate::Dynamic),
+            other => other.parse().map(Candidate::Static).map_err(|_| {
+                D::Error::custom(format_args!(
+                    "expected \"dynamic\" or an ip address, got {other:?}"
+                ))
+            }),
+        }
+    }
+}
+
+/// how a failover candidate is checked
+#[derive(Eq, Ord, PartialOrd, PartialEq, Debug, Clone)]
+pub enum Probe {
+    /// a tcp connection to this port
+    Tcp(u16),
+    /// a GET on this url answering with a 2xx, with the url's host pinned to the candidate
+    Http(Url),
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum ProbeInner {
+    Tcp(u16),
+    Http(Box<str>),
+}
+
+/// keeps the record on the highest priority candidate that passes its health probe
+#[derive(Eq, Ord, PartialOrd, PartialEq, Debug, Clone)]
+pub struct Failover {
+    candidates: Box<[Candidate]>,
+    probe: Probe,
+    interval: Duration,
+    timeout: Duration,
+    rise: u8,
+    fall: u8,
+}
+
+impl Failover {
+    const DEFAULT_INTERVAL: Duration = Duration::from_secs(30);
+    const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+    const DEFAULT_RISE: u8 = 3;
+    const DEFAULT_FALL: u8 = 2;
+
+    /// in order of priority, the first one is preferred
+    pub fn candidates(&self) -> &[Candidate] {
+        &self.candidates
+    }
+
+    pub fn probe(&self) -> &Probe {
+        &self.probe
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// how many probes in a row have to pass before a down candidate counts as healthy again
+    pub fn rise(&self) -> u8 {
+        self.rise
+    }
+
+    /// how many probes in a row have to fail before a candidate counts as down
+    pub fn fall(&self) -> u8 {
+        self.fall
+    }
+}
+
+#[derive(Deserialize)]
+struct FailoverInner {
+    candidates: Vec<Candidate>,
+    probe: ProbeInner,
+    #[serde(default)]
+    interval: Option<Time>,
+    #[serde(default)]
+    timeout: Option<Time>,
+    #[serde(default)]
+    rise: Option<u8>,
+    #[serde(default)]
+    fall: Option<u8>,
+}
 
-            #[serde(default)]
-            proxied: bool,
+impl FailoverInner {
+    fn into_failover<E: Error>(self, family: Families) -> Result<Failover, E> {
+        let FailoverInner {
+            candidates,
+            probe,
+            interval,
+            timeout,
+            rise,
+            fall,
+        } = self;
+
+        for family in family.iter() {
+            let usable = candidates.iter().any(|candidate| match candidate {
+                Candidate::Dynamic => true,
+                Candidate::Static(ip) => IpFamily::of(ip) == family,
+            });
+            if !usable {
+                return Err(E::custom(format_args!(
+                    "failover has no {family} candidate"
+                )));
+            }
+        }
+
+        let probe = match probe {
+            ProbeInner::Tcp(0) => return Err(E::custom("the tcp probe needs a port")),
+            ProbeInner::Tcp(port) => Probe::Tcp(port),
+            ProbeInner::Http(url) => {
+                let url = Url::parse(&url).map_err(E::custom)?;
+                if !matches!(url.scheme(), "http" | "https") || url.host_str().is_none() {
+                    return Err(E::custom("the http probe needs an http(s) url with a host"));
+                }
+                Probe::Http(url)
+            }
+        };
+
+        let interval = interval.map_or(Failover::DEFAULT_INTERVAL, |time| time.0);
+        let timeout = timeout.map_or(Failover::DEFAULT_TIMEOUT, |time| time.0);
+        if interval.is_zero() || timeout.is_zero() || timeout > interval {
+            return Err(E::custom(
+                "the failover timeout has to be shorter than its interval, and neither can be zero",
+            ));
         }
 
+        let rise = rise.unwrap_or(Failover::DEFAULT_RISE);
+        let fall = fall.unwrap_or(Failover::DEFAULT_FALL);
+        if rise == 0 || fall == 0 {
+            return Err(E::custom("failover rise and fall must be at least 1"));
+        }
+
+        Ok(Failover {
+            candidates: candidates.into_boxed_slice(),
+            probe,
+            interval,
+            timeout,
+            rise,
+            fall,
+        })
+    }
+}
+
+/// a TXT record kept up to date with when we last checked in,
+/// so an outside monitor can spot a host whose daemon died
+#[derive(Eq, Ord, PartialOrd, PartialEq, Debug)]
+pub struct Heartbeat {
+    name: Box<str>,
+    interval: Duration,
+}
+
+impl Heartbeat {
+    const DEFAULT_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// how long to wait between writes, unless the address changes
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum HeartbeatInner {
+    Enabled(bool),
+    Custom {
+        #[serde(default)]
+        name: Option<String>,
+        #[serde(default)]
+        interval: Option<Time>,
+    },
+}
+
+#[derive(Eq, Ord, PartialOrd, PartialEq, Debug)]
+pub struct Zone {
+    id: Option<Box<str>>,
+    name: Option<Box<str>>,
+    record: Box<str>,
+    proxied: bool,
+    ttl: Option<u32>,
+    comment: Option<Box<str>>,
+    tags: Option<Box<[Box<str>]>>,
+    family: Families,
+    create_if_missing: bool,
+    round_robin: Option<RoundRobin>,
+    svcb_hints: bool,
+    heartbeat: Option<Heartbeat>,
+    on_missing: OnMissing,
+    missing_grace: Duration,
+    outside_changes: OutsideChanges,
+    lan_host: Option<LanHost>,
+    failover: Option<Failover>,
+    account: Arc<Account>,
+}
+
+#[derive(Default, Deserialize)]
+struct ZoneInner {
+    #[serde(default)]
+    id: Option<Box<str>>,
+    #[serde(default)]
+    name: Option<String>,
+    record: String,
+
+    #[serde(default)]
+    proxied: bool,
+    #[serde(default)]
+    ttl: Option<u32>,
+    #[serde(default)]
+    comment: Option<Box<str>>,
+    #[serde(default)]
+    tags: Option<Vec<Box<str>>>,
+    #[serde(default)]
+    family: Families,
+    #[serde(default)]
+    #[serde(alias = "create-if-missing")]
+    create_if_missing: bool,
+    #[serde(default)]
+    #[serde(alias = "round-robin")]
+    round_robin: Option<RoundRobin>,
+    #[serde(default)]
+    #[serde(alias = "svcb-hints")]
+    svcb_hints: bool,
+    #[serde(default)]
+    heartbeat: Option<HeartbeatInner>,
+    #[serde(default)]
+    #[serde(alias = "on-missing")]
+    on_missing: OnMissing,
+    #[serde(default)]
+    #[serde(alias = "missing-grace")]
+    missing_grace: Option<Time>,
+    #[serde(default)]
+    #[serde(alias = "outside-changes")]
+    outside_changes: OutsideChanges,
+    #[serde(default)]
+    #[serde(alias = "ipv6-suffix")]
+    ipv6_suffix: Option<Ipv6Addr>,
+    #[serde(default)]
+    #[serde(alias = "prefix-length")]
+    prefix_length: Option<u8>,
+    #[serde(default)]
+    #[serde(alias = "prefix-from")]
+    prefix_from: Option<PrefixSource>,
+    #[serde(default)]
+    failover: Option<FailoverInner>,
+    #[serde(default)]
+    account: Option<Account>,
+}
+
+/// a domain the way cloudflare stores it, with anything outside ascii as punycode
+fn to_ascii<E: Error>(domain: &str) -> Result<Box<str>, E> {
+    idna::domain_to_ascii(domain)
+        .map(String::into_boxed_str)
+        .map_err(|_| E::custom("Invalid UTS #46 domain"))
+}
+
+fn check_ttl<E: Error>(ttl: Option<u32>) -> Result<(), E> {
+    match ttl {
+        Some(ttl) if ttl != 1 && !(30..=86400).contains(&ttl) => Err(E::custom(
+            "ttl must be between 30 and 86400 seconds, or 1 for automatic",
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// the entry's own account, or the top level one when it has none,
+/// `what` names the entry and `section` where its own account would go
+fn resolve_account<E: Error>(
+    account: Option<Account>,
+    default_account: Option<&Arc<Account>>,
+    what: std::fmt::Arguments,
+    section: &str,
+) -> Result<Arc<Account>, E> {
+    match (account, default_account) {
+        (Some(account), _) => Ok(Arc::new(account)),
+        (None, Some(account)) => Ok(Arc::clone(account)),
+        (None, None) => Err(E::custom(format_args!(
+            "{what} has no account, add a top level [account] or give {section} its own"
+        ))),
+    }
+}
+
+impl ZoneInner {
+    /// builds the zone, or one zone for each family when the record name holds `{family}`
+    fn into_zones<E: Error>(self, default_account: Option<&Arc<Account>>) -> Result<Vec<Zone>, E> {
         let ZoneInner {
             id,
+            name,
             record,
             proxied,
-        } = ZoneInner::deserialize(deserializer)?;
+            ttl,
+            mut comment,
+            mut tags,
+            family,
+            create_if_missing,
+            round_robin,
+            svcb_hints,
+            heartbeat,
+            on_missing,
+            missing_grace,
+            outside_changes,
+            ipv6_suffix,
+            prefix_length,
+            prefix_from,
+            failover,
+            account,
+        } = self;
 
-        let record = idna::domain_to_ascii(&record)
-            .map_err(|_| Error::custom("Invalid UTS #46 domain"))?
-            .into_boxed_str();
+        let name = name.as_deref().map(to_ascii).transpose()?;
+        check_ttl(ttl)?;
 
-        Ok(Zone {
-            id,
-            record,
-            proxied,
-        })
+        if proxied && ttl.is_some_and(|ttl| ttl != 1) {
+            return Err(E::custom("proxied records always use an automatic ttl"));
+        }
+
+        // the round-robin marker is part of the settings we keep on the record
+        match &round_robin {
+            Some(RoundRobin::Comment(marker)) => match &comment {
+                Some(comment) if comment != marker => {
+                    return Err(E::custom(
+                        "comment conflicts with the comment used by round-robin",
+                    ))
+                }
+                _ => comment = Some(marker.clone()),
+            },
+            Some(RoundRobin::Tag(marker)) => {
+                if let Some(tags) = &mut tags {
+                    if !tags.contains(marker) {
+                        tags.push(marker.clone())
+                    }
+                }
+            }
+            Some(RoundRobin::LastIp) | None => {}
+        }
+
+        match &on_missing {
+            OnMissing::Delete if !create_if_missing => {
+                return Err(E::custom(
+                    "on-missing = \"delete\" needs create-if-missing, \
+                     so the record comes back along with the address",
+                ))
+            }
+            OnMissing::Placeholder(placeholders) => {
+                for family in family.iter() {
+                    let count = placeholders
+                        .iter()
+                        .filter(|ip| IpFamily::of(ip) == family)
+                        .count();
+                    if count != 1 {
+                        return Err(E::custom(format_args!(
+                            "on-missing needs exactly one {family} placeholder for {record}, got {count}"
+                        )));
+                    }
+                }
+            }
+            OnMissing::Keep | OnMissing::Delete => {}
+        }
+
+        let lan_host = match ipv6_suffix {
+            Some(suffix) => {
+                if !family.contains(IpFamily::V6) {
+                    return Err(E::custom("ipv6-suffix needs the v6 family"));
+                }
+                let prefix_length = prefix_length.unwrap_or(LanHost::DEFAULT_PREFIX_LENGTH);
+                if !(1..=127).contains(&prefix_length) {
+                    return Err(E::custom("prefix-length must be between 1 and 127"));
+                }
+                Some(LanHost {
+                    suffix,
+                    prefix_length,
+                    prefix_from: prefix_from.unwrap_or_default(),
+                })
+            }
+            None if prefix_length.is_some() || prefix_from.is_some() => {
+                return Err(E::custom(
+                    "prefix-length and prefix-from only apply alongside ipv6-suffix",
+                ))
+            }
+            None => None,
+        };
+
+        let failover = failover
+            .map(|failover| failover.into_failover(family))
+            .transpose()?;
+
+        let tags = tags.map(|mut tags| {
+            tags.sort_unstable();
+            tags.dedup();
+            tags.into_boxed_slice()
+        });
+
+        let account = resolve_account(
+            account,
+            default_account,
+            format_args!("the record {record}"),
+            "the zone",
+        )?;
+
+        // a record split up by family manages a single family under each name
+        let split = match record.contains("{family}") {
+            true => family
+                .iter()
+                .map(|family| (Some(family), Families::from(family)))
+                .collect(),
+            false => vec![(None, family)],
+        };
+
+        let mut zones = Vec::with_capacity(split.len());
+        for (split_family, family) in split {
+            // placeholders are expanded before the name goes through idna like any other
+            let expand =
+                |template: &str| template::expand(template, split_family).map_err(E::custom);
+            let record = to_ascii(&expand(&record)?)?;
+
+            if let Some(name) = &name {
+                if !util::is_subdomain(&record, name) {
+                    return Err(E::custom(format_args!(
+                        "the record {record} is not part of the zone {name}"
+                    )));
+                }
+            }
+
+            let heartbeat = match &heartbeat {
+                None | Some(HeartbeatInner::Enabled(false)) => None,
+                Some(HeartbeatInner::Enabled(true)) => Some(Heartbeat {
+                    name: format!("_ddns.{record}").into_boxed_str(),
+                    interval: Heartbeat::DEFAULT_INTERVAL,
+                }),
+                Some(HeartbeatInner::Custom { name, interval }) => Some(Heartbeat {
+                    name: match name {
+                        // every family publishes its own heartbeat, they can't share a name
+                        Some(name) if split_family.is_some() && !name.contains("{family}") => {
+                            return Err(E::custom(
+                                "the heartbeat name needs {family} when the record name has it",
+                            ))
+                        }
+                        Some(name) => to_ascii(&expand(name)?)?,
+                        None => format!("_ddns.{record}").into_boxed_str(),
+                    },
+                    interval: interval
+                        .as_ref()
+                        .map_or(Heartbeat::DEFAULT_INTERVAL, |time| time.0),
+                }),
+            };
+
+            zones.push(Zone {
+                id: id.clone(),
+                name: name.clone(),
+                record,
+                proxied,
+                ttl,
+                comment: comment.clone(),
+                tags: tags.clone(),
+                family,
+                create_if_missing,
+                round_robin: round_robin.clone(),
+                svcb_hints,
+                heartbeat,
+                on_missing: on_missing.clone(),
+                missing_grace: missing_grace
+                    .as_ref()
+                    .map_or(Zone::DEFAULT_MISSING_GRACE, |time| time.0),
+                outside_changes,
+                lan_host: lan_host.clone(),
+                failover: failover.clone(),
+                account: Arc::clone(&account),
+            });
+        }
+
+        Ok(zones)
+    }
+}
+
+/// builds a [`Zone`] in code for [`ApiFieldsBuilder::zone`], everything left out takes the default of `api.toml`
+pub struct ZoneBuilder(ZoneInner);
+
+impl ZoneBuilder {
+    /// the zone id, looked up from the zone name or the record otherwise
+    pub fn id(mut self, id: &str) -> Self {
+        self.0.id = Some(Box::from(id));
+        self
+    }
+
+    /// the zone name, like `example.com`
+    pub fn name(mut self, name: &str) -> Self {
+        self.0.name = Some(name.to_owned());
+        self
+    }
+
+    pub fn proxied(mut self, proxied: bool) -> Self {
+        self.0.proxied = proxied;
+        self
+    }
+
+    /// in seconds, 1 is automatic
+    pub fn ttl(mut self, ttl: u32) -> Self {
+        self.0.ttl = Some(ttl);
+        self
+    }
+
+    pub fn comment(mut self, comment: &str) -> Self {
+        self.0.comment = Some(Box::from(comment));
+        self
+    }
+
+    pub fn tags(mut self, tags: impl IntoIterator<Item = impl Into<Box<str>>>) -> Self {
+        self.0.tags = Some(tags.into_iter().map(Into::into).collect());
+        self
+    }
+
+    pub fn family(mut self, family: Families) -> Self {
+        self.0.family = family;
+        self
+    }
+
+    pub fn create_if_missing(mut self, create_if_missing: bool) -> Self {
+        self.0.create_if_missing = create_if_missing;
+        self
+    }
+
+    /// only manage our own entry of a record set holding several values
+    pub fn round_robin(mut self, round_robin: RoundRobin) -> Self {
+        self.0.round_robin = Some(round_robin);
+        self
+    }
+
+    /// also keep the address hints of the HTTPS and SVCB records with the same name up to date
+    pub fn svcb_hints(mut self, svcb_hints: bool) -> Self {
+        self.0.svcb_hints = svcb_hints;
+        self
+    }
+
+    /// keep a heartbeat TXT record at `_ddns.<record>`, written every 15 minutes or when an address changes
+    pub fn heartbeat(mut self, heartbeat: bool) -> Self {
+        self.0.heartbeat = Some(HeartbeatInner::Enabled(heartbeat));
+        self
+    }
+
+    /// what happens to the record once an address family is gone for the missing grace
+    pub fn on_missing(mut self, on_missing: OnMissing) -> Self {
+        self.0.on_missing = on_missing;
+        self
+    }
+
+    /// how long an address family has to be gone before on-missing kicks in, 30 minutes by default
+    pub fn missing_grace(mut self, missing_grace: Duration) -> Self {
+        self.0.missing_grace = Some(Time(missing_grace));
+        self
+    }
+
+    pub fn outside_changes(mut self, outside_changes: OutsideChanges) -> Self {
+        self.0.outside_changes = outside_changes;
+        self
+    }
+
+    /// makes the record a lan host, its AAAA record is the current prefix followed by this suffix
+    pub fn ipv6_suffix(mut self, suffix: Ipv6Addr) -> Self {
+        self.0.ipv6_suffix = Some(suffix);
+        self
+    }
+
+    /// how long the delegated prefix of a lan host is, 64 by default
+    pub fn prefix_length(mut self, prefix_length: u8) -> Self {
+        self.0.prefix_length = Some(prefix_length);
+        self
+    }
+
+    /// where the prefix of a lan host is taken from, the ip sources by default
+    pub fn prefix_from(mut self, prefix_from: PrefixSource) -> Self {
+        self.0.prefix_from = Some(prefix_from);
+        self
+    }
+
+    /// the account for this zone alone, instead of the top level one
+    pub fn account(mut self, account: Account) -> Self {
+        self.0.account = Some(account);
+        self
     }
 }
+
 impl Zone {
-    pub fn id(&self) -> &str {
-        &self.id
+    const DEFAULT_MISSING_GRACE: Duration = Duration::from_secs(30 * 60);
+
+    /// the zone keeping `record` up to date, a template like in `api.toml`
+    pub fn builder(record: &str) -> ZoneBuilder {
+        ZoneBuilder(ZoneInner {
+            record: record.to_owned(),
+            ..ZoneInner::default()
+        })
+    }
+
+    /// a zone holding a single record that has nothing to do with our address,
+    /// only the lookup and the credentials matter on it
+    pub(crate) fn bare(
+        id: Option<Box<str>>,
+        name: Option<Box<str>>,
+        record: Box<str>,
+        account: Arc<Account>,
+    ) -> Zone {
+        Zone {
+            id,
+            name,
+            record,
+            proxied: false,
+            ttl: None,
+            comment: None,
+            tags: None,
+            family: Families::default(),
+            create_if_missing: true,
+            round_robin: None,
+            svcb_hints: false,
+            heartbeat: None,
+            on_missing: OnMissing::Keep,
+            missing_grace: Zone::DEFAULT_MISSING_GRACE,
+            outside_changes: OutsideChanges::default(),
+            lan_host: None,
+            failover: None,
+            account,
+        }
+    }
+
+    /// the zone id, if it was given in the config
+    pub fn id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+
+    /// the zone name, if it was given in the config
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
     }
 
     pub fn record(&self) -> &str {
@@ -105,16 +869,794 @@ impl Zone {
     pub fn proxied(&self) -> bool {
         self.proxied
     }
+
+    /// the managed ttl, `None` leaves it as it is
+    pub fn ttl(&self) -> Option<u32> {
+        self.ttl
+    }
+
+    /// the managed comment, `None` leaves it as it is
+    pub fn comment(&self) -> Option<&str> {
+        self.comment.as_deref()
+    }
+
+    /// the managed tags sorted and deduplicated, `None` leaves them as they are
+    pub fn tags(&self) -> Option<&[Box<str>]> {
+        self.tags.as_deref()
+    }
+
+    pub fn family(&self) -> Families {
+        self.family
+    }
+
+    pub fn create_if_missing(&self) -> bool {
+        self.create_if_missing
+    }
+
+    /// set when the record name holds several values, and we only own one of them
+    pub fn round_robin(&self) -> Option<&RoundRobin> {
+        self.round_robin.as_ref()
+    }
+
+    pub fn heartbeat(&self) -> Option<&Heartbeat> {
+        self.heartbeat.as_ref()
+    }
+
+    /// whether the HTTPS and SVCB records with this name get their address hints rewritten
+    pub fn svcb_hints(&self) -> bool {
+        self.svcb_hints
+    }
+
+    pub fn on_missing(&self) -> &OnMissing {
+        &self.on_missing
+    }
+
+    /// how long an address family has to stay unresolvable before `on_missing` kicks in
+    pub fn missing_grace(&self) -> Duration {
+        self.missing_grace
+    }
+
+    pub fn outside_changes(&self) -> OutsideChanges {
+        self.outside_changes
+    }
+
+    /// set when the AAAA record belongs to a host behind the router rather than to us
+    pub fn lan_host(&self) -> Option<&LanHost> {
+        self.lan_host.as_ref()
+    }
+
+    /// set when the record is switched between candidates by their health
+    pub fn failover(&self) -> Option<&Failover> {
+        self.failover.as_ref()
+    }
+
+    pub fn account(&self) -> &Account {
+        &self.account
+    }
+
+    pub(crate) fn shared_account(&self) -> &Arc<Account> {
+        &self.account
+    }
+}
+
+/// a record declared under `[[records]]`, kept exactly as configured on every update
+#[derive(Eq, Ord, PartialOrd, PartialEq, Debug)]
+pub struct StaticRecord {
+    zone: Zone,
+    record_type: Box<str>,
+    content: Box<str>,
+    priority: Option<u16>,
+}
+
+#[derive(Deserialize)]
+struct StaticRecordInner {
+    name: String,
+    #[serde(rename = "type")]
+    record_type: Box<str>,
+    content: Box<str>,
+    #[serde(default)]
+    priority: Option<u16>,
+    #[serde(default)]
+    proxied: bool,
+    #[serde(default)]
+    ttl: Option<u32>,
+    #[serde(default)]
+    comment: Option<Box<str>>,
+    #[serde(default)]
+    tags: Option<Vec<Box<str>>>,
+    #[serde(default)]
+    zone: Option<String>,
+    #[serde(default)]
+    #[serde(alias = "zone-id")]
+    zone_id: Option<Box<str>>,
+    #[serde(default)]
+    account: Option<Account>,
+}
+
+impl StaticRecordInner {
+    fn into_static_record<E: Error>(
+        self,
+        default_account: Option<&Arc<Account>>,
+    ) -> Result<StaticRecord, E> {
+        let StaticRecordInner {
+            name,
+            record_type,
+            content,
+            priority,
+            proxied,
+            ttl,
+            comment,
+            tags,
+            zone,
+            zone_id,
+            account,
+        } = self;
+
+        let record = to_ascii(&name)?;
+        let zone = zone.as_deref().map(to_ascii).transpose()?;
+
+        if let Some(zone) = &zone {
+            if !util::is_subdomain(&record, zone) {
+                return Err(E::custom(format_args!(
+                    "the record {record} is not part of the zone {zone}"
+                )));
+            }
+        }
+
+        let record_type = record_type.to_ascii_uppercase().into_boxed_str();
+        match &*record_type {
+            "A" | "AAAA" => {
+                let ip = content.parse::<IpAddr>().ok();
+                if ip.map(|ip| IpFamily::of(&ip).record_type()) != Some(&*record_type) {
+                    return Err(E::custom(format_args!(
+                        "the {record_type} record {record} needs an {} address as its content",
+                        match &*record_type {
+                            "A" => "ipv4",
+                            _ => "ipv6",
+                        }
+                    )));
+                }
+            }
+            "CNAME" | "TXT" | "MX" | "NS" | "PTR" => {}
+            _ => {
+                return Err(E::custom(format_args!(
+                    "records of type {record_type} can't be declared, \
+                     only A, AAAA, CNAME, TXT, MX, NS and PTR are supported"
+                )))
+            }
+        }
+
+        if (&*record_type == "MX") != priority.is_some() {
+            return Err(E::custom(format_args!(
+                "{record}: MX records need a priority, and only they take one"
+            )));
+        }
+
+        if proxied && !StaticRecord::proxiable(&record_type) {
+            return Err(E::custom(format_args!(
+                "{record}: only A, AAAA and CNAME records can be proxied"
+            )));
+        }
+
+        check_ttl(ttl)?;
+
+        let account = resolve_account(
+            account,
+            default_account,
+            format_args!("the record {record}"),
+            "the record",
+        )?;
+
+        let mut zone = Zone::bare(zone_id, zone, record, account);
+        zone.proxied = proxied;
+        zone.ttl = ttl;
+        zone.comment = comment;
+        zone.tags = tags.map(|mut tags| {
+            tags.sort_unstable();
+            tags.dedup();
+            tags.into_boxed_slice()
+        });
+
+        Ok(StaticRecord {
+            zone,
+            record_type,
+            content,
+            priority,
+        })
+    }
+}
+
+impl StaticRecord {
+    /// whether cloudflare can proxy records of this type
+    pub(crate) fn proxiable(record_type: &str) -> bool {
+        matches!(record_type, "A" | "AAAA" | "CNAME")
+    }
+
+    /// where the record lives, along with its name and the proxied, ttl, comment and tags to keep on it
+    pub fn zone(&self) -> &Zone {
+        &self.zone
+    }
+
+    pub fn name(&self) -> &str {
+        self.zone.record()
+    }
+
+    pub fn record_type(&self) -> &str {
+        &self.record_type
+    }
+
+    pub fn content(&self) -> &str {
+        &self.content
+    }
+
+    /// the priority of an MX record
+    pub fn priority(&self) -> Option<u16> {
+        self.priority
+    }
+}
+
+/// accepts both a single `[zone]` table and an array of `[[zone]]` tables
+struct OneOrMany<T>(Vec<T>);
+
+impl<T> Default for OneOrMany<T> {
+    fn default() -> Self {
+        OneOrMany(vec![])
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for OneOrMany<T> {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct OneOrManyVisitor<T>(PhantomData<T>);
+
+        impl<'de, T: Deserialize<'de>> Visitor<'de> for OneOrManyVisitor<T> {
+            type Value = OneOrMany<T>;
+
+            fn expecting(&self, formatter: &mut Formatter) -> std::fmt::Result {
+                formatter.write_str("a table or an array of tables")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+                Vec::deserialize(SeqAccessDeserializer::new(seq)).map(OneOrMany)
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                T::deserialize(MapAccessDeserializer::new(map)).map(|one| OneOrMany(vec![one]))
+            }
+        }
+
+        deserializer.deserialize_any(OneOrManyVisitor(PhantomData))
+    }
+}
+
+/// an account level ip list, used by waf rules and firewall allowlists,
+/// where our entry is replaced whenever the address changes
+#[derive(Eq, Ord, PartialOrd, PartialEq, Debug)]
+pub struct IpList {
+    account_id: Box<str>,
+    name: Box<str>,
+    family: Families,
+    comment: Option<Box<str>>,
+    account: Arc<Account>,
+}
+
+#[derive(Deserialize)]
+struct IpListInner {
+    #[serde(alias = "account-id")]
+    account_id: Box<str>,
+    name: Box<str>,
+    #[serde(default)]
+    family: Families,
+    #[serde(default)]
+    comment: Option<Box<str>>,
+    #[serde(default)]
+    account: Option<Account>,
+}
+
+impl IpListInner {
+    fn into_ip_list<E: Error>(self, default_account: Option<&Arc<Account>>) -> Result<IpList, E> {
+        let IpListInner {
+            account_id,
+            name,
+            family,
+            comment,
+            account,
+        } = self;
+
+        let account = resolve_account(
+            account,
+            default_account,
+            format_args!("the ip list {name}"),
+            "the list",
+        )?;
+
+        Ok(IpList {
+            account_id,
+            name,
+            family,
+            comment,
+            account,
+        })
+    }
+}
+
+impl IpList {
+    /// the id of the cloudflare account that owns the list
+    pub fn account_id(&self) -> &str {
+        &self.account_id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn family(&self) -> Families {
+        self.family
+    }
+
+    /// the comment our entries carry, they're found through the last published address otherwise
+    pub fn comment(&self) -> Option<&str> {
+        self.comment.as_deref()
+    }
+
+    pub fn account(&self) -> &Account {
+        &self.account
+    }
+}
+
+/// confirming that an update went through, by reading it back from the api
+/// and asking the authoritative nameservers until they serve it
+#[derive(Eq, Ord, PartialOrd, PartialEq, Debug, Deserialize)]
+pub struct VerifyConfig {
+    #[serde(default = "VerifyConfig::default_timeout")]
+    timeout: Time,
+    #[serde(default)]
+    nameservers: Box<[Box<str>]>,
+}
+
+impl VerifyConfig {
+    const fn default_timeout() -> Time {
+        Time(Duration::from_secs(2 * 60))
+    }
+
+    /// how long the nameservers get to start serving the new address
+    pub fn timeout(&self) -> Duration {
+        self.timeout.0
+    }
+
+    /// the nameservers to ask, as `host`, `ip` or `ip:port`, the zone's own nameservers when empty
+    pub fn nameservers(&self) -> &[Box<str>] {
+        &self.nameservers
+    }
+}
+
+/// how a cycle finds out that a record is out of date
+#[derive(Eq, Ord, PartialOrd, PartialEq, Debug, Copy, Clone, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DetectMode {
+    /// read the records from the api every cycle
+    #[default]
+    Api,
+    /// ask the authoritative nameservers if they serve the current address
+    Dns,
+    /// compare the current address against the last one we published
+    Cache,
+}
+
+/// skipping the api while nothing changed, with a full reconciliation through the api now and then
+#[derive(Eq, Ord, PartialOrd, PartialEq, Debug, Deserialize)]
+pub struct DetectConfig {
+    #[serde(default)]
+    mode: DetectMode,
+    #[serde(default = "DetectConfig::default_reconcile")]
+    reconcile: Time,
+    #[serde(default)]
+    nameservers: Box<[Box<str>]>,
 }
 
-#[derive(Eq, Ord, PartialOrd, PartialEq, Deserialize, Debug)]
+impl DetectConfig {
+    const fn default_reconcile() -> Time {
+        Time(Duration::from_secs(6 * 60 * 60))
+    }
+
+    pub fn mode(&self) -> DetectMode {
+        self.mode
+    }
+
+    /// how often every record is read from the api anyway, which also corrects drift
+    pub fn reconcile(&self) -> Duration {
+        self.reconcile.0
+    }
+
+    /// the nameservers to ask in dns mode, the zone's own nameservers when empty
+    pub fn nameservers(&self) -> &[Box<str>] {
+        &self.nameservers
+    }
+}
+
+/// several instances sharing the same records, only the one holding the lease publishes
+#[derive(Eq, Ord, PartialOrd, PartialEq, Debug)]
+pub struct HaConfig {
+    zone: Zone,
+    instance: Box<str>,
+    lease: Duration,
+}
+
+#[derive(Deserialize)]
+struct HaConfigInner {
+    record: String,
+    #[serde(default)]
+    instance: Option<String>,
+    #[serde(default)]
+    lease: Option<Time>,
+    #[serde(default)]
+    zone: Option<String>,
+    #[serde(default)]
+    #[serde(alias = "zone-id")]
+    zone_id: Option<Box<str>>,
+    #[serde(default)]
+    account: Option<Account>,
+}
+
+impl HaConfigInner {
+    fn into_ha<E: Error>(self, default_account: Option<&Arc<Account>>) -> Result<HaConfig, E> {
+        let HaConfigInner {
+            record,
+            instance,
+            lease,
+            zone,
+            zone_id,
+            account,
+        } = self;
+
+        let record = to_ascii(&template::expand(&record, None).map_err(E::custom)?)?;
+        let zone = zone.as_deref().map(to_ascii).transpose()?;
+
+        if let Some(zone) = &zone {
+            if !util::is_subdomain(&record, zone) {
+                return Err(E::custom(format_args!(
+                    "the lease record {record} is not part of the zone {zone}"
+                )));
+            }
+        }
+
+        let instance = template::expand(instance.as_deref().unwrap_or("{hostname}"), None)
+            .map_err(E::custom)?;
+        if instance.is_empty() || instance.contains(|c: char| c.is_whitespace() || c == '"') {
+            return Err(E::custom(
+                "the ha instance id can't be empty, or hold whitespace or quotes",
+            ));
+        }
+
+        let lease = lease.map_or(HaConfig::DEFAULT_LEASE, |time| time.0);
+        if lease < HaConfig::MIN_LEASE {
+            return Err(E::custom("the ha lease has to last at least 15 seconds"));
+        }
+
+        let account = resolve_account(
+            account,
+            default_account,
+            format_args!("the ha lease"),
+            "[ha]",
+        )?;
+
+        Ok(HaConfig {
+            zone: Zone::bare(zone_id, zone, record, account),
+            instance: instance.into_boxed_str(),
+            lease,
+        })
+    }
+}
+
+impl HaConfig {
+    const DEFAULT_LEASE: Duration = Duration::from_secs(90);
+    const MIN_LEASE: Duration = Duration::from_secs(15);
+
+    /// where the TXT record holding the lease lives, the record itself is its name
+    pub fn zone(&self) -> &Zone {
+        &self.zone
+    }
+
+    pub fn record(&self) -> &str {
+        self.zone.record()
+    }
+
+    /// the id this instance writes into the lease
+    pub fn instance(&self) -> &str {
+        &self.instance
+    }
+
+    /// how long a lease lasts without being renewed
+    pub fn lease(&self) -> Duration {
+        self.lease
+    }
+}
+
+#[derive(Eq, Ord, PartialOrd, PartialEq, Debug)]
 pub struct ApiFields {
-    pub(crate) account: Account,
-    pub(crate) zone: Zone,
+    pub(crate) api_base: Url,
+    pub(crate) account: Option<Arc<Account>>,
+    pub(crate) zones: Box<[Zone]>,
+    pub(crate) records: Box<[StaticRecord]>,
+    pub(crate) ip_lists: Box<[IpList]>,
+    pub(crate) verify: Option<VerifyConfig>,
+    pub(crate) detect: Option<DetectConfig>,
+    pub(crate) ha: Option<HaConfig>,
+}
+
+#[derive(Deserialize)]
+struct ApiFieldsInner {
+    #[serde(default)]
+    #[serde(alias = "api-base")]
+    api_base: Option<Box<str>>,
+    account: Option<Account>,
+    #[serde(default)]
+    #[serde(alias = "zones")]
+    zone: OneOrMany<ZoneInner>,
+    #[serde(default)]
+    #[serde(alias = "ip-list", alias = "ip-lists")]
+    ip_list: OneOrMany<IpListInner>,
+    #[serde(default)]
+    #[serde(alias = "record")]
+    records: OneOrMany<StaticRecordInner>,
+    #[serde(default)]
+    verify: Option<VerifyConfig>,
+    #[serde(default)]
+    #[serde(alias = "change-detection")]
+    detect: Option<DetectConfig>,
+    #[serde(default)]
+    #[serde(alias = "high-availability")]
+    ha: Option<HaConfigInner>,
+}
+
+impl ApiFieldsInner {
+    fn into_api_fields<E: Error>(self) -> Result<ApiFields, E> {
+        let ApiFieldsInner {
+            api_base,
+            account,
+            zone,
+            ip_list,
+            records,
+            verify,
+            detect,
+            ha,
+        } = self;
+
+        let api_base = Url::parse(api_base.as_deref().unwrap_or(API_BASE))
+            .map_err(|e| Error::custom(format_args!("invalid api-base: {e}")))?;
+        if api_base.cannot_be_a_base() || !matches!(api_base.scheme(), "http" | "https") {
+            return Err(Error::custom("api-base must be an http(s) url"));
+        }
+
+        if zone.0.is_empty() && ip_list.0.is_empty() && records.0.is_empty() {
+            return Err(Error::custom(
+                "expected at least one zone, ip-list or record",
+            ));
+        }
+
+        let account = account.map(Arc::new);
+        let zones = zone
+            .0
+            .into_iter()
+            .map(|zone| zone.into_zones(account.as_ref()))
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .flatten()
+            .collect::<Box<[Zone]>>();
+        let ip_lists = ip_list
+            .0
+            .into_iter()
+            .map(|list| list.into_ip_list(account.as_ref()))
+            .collect::<Result<_, _>>()?;
+        let records = records
+            .0
+            .into_iter()
+            .map(|record| record.into_static_record(account.as_ref()))
+            .collect::<Result<Box<[StaticRecord]>, _>>()?;
+
+        // a declared address record would fight the daemon over the same name
+        for record in records.iter() {
+            let taken = zones.iter().any(|zone: &Zone| {
+                zone.record() == record.name()
+                    && zone
+                        .family()
+                        .iter()
+                        .any(|family| family.record_type() == record.record_type())
+            });
+            if taken {
+                return Err(Error::custom(format_args!(
+                    "the {} record {} is already kept up to date by a [[zone]]",
+                    record.record_type(),
+                    record.name()
+                )));
+            }
+        }
+
+        let ha = ha.map(|ha| ha.into_ha(account.as_ref())).transpose()?;
+
+        Ok(ApiFields {
+            api_base,
+            account,
+            zones,
+            records,
+            ip_lists,
+            verify,
+            detect,
+            ha,
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for ApiFields {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        ApiFieldsInner::deserialize(deserializer)?.into_api_fields()
+    }
+}
+
+impl ApiFields {
+    /// starts api fields built in code instead of read from `api.toml`
+    ///
+    /// only the account, the zones and `api-base` can be built this way, a custom heartbeat or failover,
+    /// `[[ip-list]]`, `[[records]]`, `[verify]`, `[detect]` and `[ha]` have to be parsed from toml with [`str::parse`]
+    ///
+    /// ```
+    /// use cloudflare_ddns::{Account, ApiFields, Zone};
+    ///
+    /// let api_fields = ApiFields::builder()
+    ///     .account(Account::token("8dY3nH-As0krmv83n3pm1l")?)
+    ///     .zone(Zone::builder("home.example.com").proxied(true))
+    ///     .build()?;
+    /// # anyhow::Ok(())
+    /// ```
+    pub fn builder() -> ApiFieldsBuilder {
+        ApiFieldsBuilder {
+            api_base: None,
+            account: None,
+            zones: vec![],
+        }
+    }
+}
+
+/// builds [`ApiFields`] in code, see [`ApiFields::builder`]
+pub struct ApiFieldsBuilder {
+    api_base: Option<Url>,
+    account: Option<Account>,
+    zones: Vec<ZoneInner>,
+}
+
+impl ApiFieldsBuilder {
+    /// like `api-base`, where the cloudflare api is reached
+    pub fn api_base(mut self, api_base: Url) -> Self {
+        self.api_base = Some(api_base);
+        self
+    }
+
+    /// like the top level `[account]`, used by every zone that has none of its own
+    pub fn account(mut self, account: Account) -> Self {
+        self.account = Some(account);
+        self
+    }
+
+    /// like a `[[zone]]`, zones are kept up to date in the order they're added
+    pub fn zone(mut self, zone: ZoneBuilder) -> Self {
+        self.zones.push(zone.0);
+        self
+    }
+
+    /// checks everything the way `api.toml` is checked
+    pub fn build(self) -> Result<ApiFields> {
+        let inner = ApiFieldsInner {
+            api_base: self.api_base.map(|url| Box::from(url.as_str())),
+            account: self.account,
+            zone: OneOrMany(self.zones),
+            ip_list: OneOrMany::default(),
+            records: OneOrMany::default(),
+            verify: None,
+            detect: None,
+            ha: None,
+        };
+        Ok(inner.into_api_fields::<value::Error>()?)
+    }
+}
+
+impl FromStr for ApiFields {
+    type Err = toml::de::Error;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        toml::de::from_str(text)
+    }
 }
 
 impl Deserializable for ApiFields {
     async fn deserialize(text: &str) -> Result<Self> {
-        Ok(toml::de::from_str(text)?)
+        Ok(text.parse()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn account(toml: &str) -> Result<Account, toml::de::Error> {
+        toml::from_str(toml)
+    }
+
+    #[test]
+    fn tokens_dont_need_an_email() {
+        let token = account(r#"api-token = "abc""#).unwrap();
+        assert!(token.uses_token());
+        assert_eq!(token.email, None);
+    }
+
+    #[test]
+    fn keys_need_an_email() {
+        assert!(account(r#"auth-key = "abc""#).is_err());
+        let key = account(
+            r#"
+            email = "me@example.com"
+            auth-key = "abc"
+            "#,
+        )
+        .unwrap();
+        assert!(!key.uses_token());
+    }
+
+    #[test]
+    fn takes_a_token_or_a_key_but_not_both() {
+        assert!(account(r#"email = "me@example.com""#).is_err());
+        assert!(account(
+            r#"
+            email = "me@example.com"
+            api-token = "abc"
+            auth-key = "abc"
+            "#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn builds_the_same_zones_as_toml() {
+        let parsed: ApiFields = r#"
+            [account]
+            api-token = "8dY3nH-As0krmv83n3pm1l"
+
+            [[zone]]
+            record = "home.example.com"
+            family = "both"
+            create-if-missing = true
+            round-robin = { comment = "home" }
+            svcb-hints = true
+            heartbeat = true
+            on-missing = "delete"
+            missing-grace = 01:00:00
+            ipv6-suffix = "::1234"
+            prefix-length = 56
+            "#
+        .parse()
+        .unwrap();
+
+        let built = ApiFields::builder()
+            .account(Account::token("8dY3nH-As0krmv83n3pm1l").unwrap())
+            .zone(
+                Zone::builder("home.example.com")
+                    .family(Families::Both)
+                    .create_if_missing(true)
+                    .round_robin(RoundRobin::Comment("home".into()))
+                    .svcb_hints(true)
+                    .heartbeat(true)
+                    .on_missing(OnMissing::Delete)
+                    .missing_grace(Duration::from_secs(60 * 60))
+                    .ipv6_suffix("::1234".parse().unwrap())
+                    .prefix_length(56),
+            )
+            .build()
+            .unwrap();
+
+        assert_eq!(built, parsed);
     }
 }
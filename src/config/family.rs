@@ -0,0 +1,76 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::net::IpAddr;
+
+/// A single address family, and by extension the DNS record type that holds it
+#[derive(
+    Debug, Default, Copy, Clone, Eq, Ord, PartialOrd, PartialEq, Hash, Serialize, Deserialize,
+)]
+pub enum IpFamily {
+    #[default]
+    #[serde(rename = "v4", alias = "ipv4", alias = "A")]
+    V4,
+    #[serde(rename = "v6", alias = "ipv6", alias = "AAAA")]
+    V6,
+}
+
+impl IpFamily {
+    pub const fn of(ip: &IpAddr) -> Self {
+        match ip {
+            IpAddr::V4(_) => IpFamily::V4,
+            IpAddr::V6(_) => IpFamily::V6,
+        }
+    }
+
+    pub const fn record_type(self) -> &'static str {
+        match self {
+            IpFamily::V4 => "A",
+            IpFamily::V6 => "AAAA",
+        }
+    }
+}
+
+impl Display for IpFamily {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            IpFamily::V4 => "IPv4",
+            IpFamily::V6 => "IPv6",
+        })
+    }
+}
+
+/// The set of address families a record manages
+#[derive(Debug, Default, Copy, Clone, Eq, Ord, PartialOrd, PartialEq, Hash, Deserialize)]
+pub enum Families {
+    #[default]
+    #[serde(rename = "v4", alias = "ipv4", alias = "A")]
+    V4,
+    #[serde(rename = "v6", alias = "ipv6", alias = "AAAA")]
+    V6,
+    #[serde(rename = "both", alias = "dual-stack")]
+    Both,
+}
+
+impl From<IpFamily> for Families {
+    fn from(family: IpFamily) -> Self {
+        match family {
+            IpFamily::V4 => Families::V4,
+            IpFamily::V6 => Families::V6,
+        }
+    }
+}
+
+impl Families {
+    pub const fn contains(self, family: IpFamily) -> bool {
+        matches!(
+            (self, family),
+            (Families::Both, _) | (Families::V4, IpFamily::V4) | (Families::V6, IpFamily::V6)
+        )
+    }
+
+    pub fn iter(self) -> impl Iterator<Item = IpFamily> {
+        [IpFamily::V4, IpFamily::V6]
+            .into_iter()
+            .filter(move |&family| self.contains(family))
+    }
+}
@@ -1,7 +1,10 @@
 use crate::config::time::Time;
 use crate::config::Deserializable;
+use crate::non_zero;
 use anyhow::Result;
 use serde::Deserialize;
+use std::num::NonZeroU32;
+use std::str::FromStr;
 use std::time::Duration;
 
 #[derive(Debug, Eq, Ord, PartialOrd, PartialEq, Deserialize)]
@@ -60,19 +63,74 @@ impl Default for ClientConfig {
     }
 }
 
+/// how many requests can be sent to the cloudflare api in a period of time,
+/// shared by every record so the token is left with room for other tools
 #[derive(Debug, Eq, Ord, PartialOrd, PartialEq, Deserialize)]
+pub struct RateLimitConfig {
+    #[serde(default = "RateLimitConfig::default_requests")]
+    requests: NonZeroU32,
+    #[serde(default = "RateLimitConfig::default_period")]
+    period: Time,
+}
+
+impl RateLimitConfig {
+    #[inline]
+    const fn default_requests() -> NonZeroU32 {
+        // cloudflare allows 1200 requests every 5 minutes per user,
+        // only take half of that by default
+        non_zero!(600)
+    }
+
+    #[inline]
+    const fn default_period() -> Time {
+        Time(Duration::from_secs(5 * 60))
+    }
+
+    pub fn requests(&self) -> NonZeroU32 {
+        self.requests
+    }
+    pub fn period(&self) -> Duration {
+        self.period.0
+    }
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            requests: Self::default_requests(),
+            period: Self::default_period(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Eq, Ord, PartialOrd, PartialEq, Deserialize)]
 pub struct HttpConfig {
     client: ClientConfig,
+    #[serde(default)]
+    #[serde(alias = "rate-limit")]
+    rate_limit: RateLimitConfig,
 }
 
 impl HttpConfig {
     pub fn client(&self) -> &ClientConfig {
         &self.client
     }
+
+    pub fn rate_limit(&self) -> &RateLimitConfig {
+        &self.rate_limit
+    }
+}
+
+impl FromStr for HttpConfig {
+    type Err = toml::de::Error;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        toml::de::from_str(text)
+    }
 }
 
 impl Deserializable for HttpConfig {
     async fn deserialize(text: &str) -> Result<Self> {
-        Ok(toml::de::from_str(text)?)
+        Ok(text.parse()?)
     }
 }
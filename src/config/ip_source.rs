@@ -1,3 +1,4 @@
+use crate::config::family::IpFamily;
 use crate::config::{Config, Deserializable};
 use crate::retrying_client::RetryingClient;
 use crate::util::{num_cpus, AddrParseError, AddrParseExt};
@@ -15,7 +16,7 @@ use std::collections::BTreeMap;
 use std::convert::Infallible;
 use std::fmt::{Debug, Formatter, Write};
 use std::future::Future;
-use std::net::Ipv4Addr;
+use std::net::IpAddr;
 use std::num::NonZeroU8;
 use std::ops::Deref;
 use std::pin::pin;
@@ -36,8 +37,10 @@ pub enum GetIpError {
     Utf8(#[from] Utf8Error),
     #[error("could not turn into a valid ip: {0}")]
     InvalidIp(#[from] AddrParseError),
-    #[error("There is no ip source to get our ip from")]
-    NoIpSources,
+    #[error("expected an {expected} address but the source returned {found}")]
+    WrongFamily { expected: IpFamily, found: IpAddr },
+    #[error("There is no {0} source to get our ip from")]
+    NoIpSources(IpFamily),
 }
 
 #[derive(PartialOrd, PartialEq, Ord, Eq)]
@@ -175,11 +178,13 @@ fn get_json_key(json: &[u8], key: &str) -> serde_json::Result<serde_json::Value>
 
 #[derive(Clone, Debug, PartialOrd, PartialEq, Ord, Eq, Serialize, Deserialize)]
 struct Process {
+    #[serde(default)]
+    family: IpFamily,
     steps: Arc<[ProcessStep]>,
 }
 
 impl Process {
-    async fn run(&self, mut bytes: Bytes, _cfg: &Config) -> Result<Ipv4Addr, GetIpError> {
+    async fn run(&self, mut bytes: Bytes, _cfg: &Config) -> Result<IpAddr, GetIpError> {
         use ProcessStep as S;
         for step in &*self.steps {
             match step {
@@ -209,11 +214,19 @@ impl Process {
             }
         }
 
-        Ok(Ipv4Addr::parse_ascii_bytes(&bytes)?)
+        let ip = IpAddr::parse_ascii_bytes(&bytes)?;
+        if IpFamily::of(&ip) != self.family {
+            return Err(GetIpError::WrongFamily {
+                expected: self.family,
+                found: ip,
+            });
+        }
+
+        Ok(ip)
     }
 }
 
-async fn into_process(mut steps: Vec<ProcessStep>) -> Process {
+async fn into_process(family: IpFamily, mut steps: Vec<ProcessStep>) -> Process {
     while let Some(ProcessStep::Plaintext) = steps.last() {
         steps.pop();
     }
@@ -237,6 +250,7 @@ async fn into_process(mut steps: Vec<ProcessStep>) -> Process {
         .await;
 
     Process {
+        family,
         steps: steps.into(),
     }
 }
@@ -248,22 +262,22 @@ pub struct Sources {
 }
 
 impl Sources {
-    pub async fn from_try_iter<I, Url, Steps, E>(
+    pub(crate) async fn from_try_iter<I, Url, Steps, E>(
         iter: I,
         concurrent_resolve: Option<NonZeroU8>,
     ) -> Result<Self>
     where
-        I: IntoIterator<Item = Result<(Url, Steps), E>>,
+        I: IntoIterator<Item = Result<(Url, IpFamily, Steps), E>>,
         E: Into<anyhow::Error>,
         Url: AsRef<str>,
         Steps: IntoIterator<Item = ProcessStep>,
     {
         futures::stream::iter(iter)
             .map(|res| async move {
-                let (url, steps) = res.map_err(Into::into)?;
+                let (url, family, steps) = res.map_err(Into::into)?;
                 Ok((
                     url::Url::parse(url.as_ref())?,
-                    into_process(steps.into_iter().collect()).await,
+                    into_process(family, steps.into_iter().collect()).await,
                 ))
             })
             .buffer_unordered(num_cpus().get())
@@ -283,12 +297,12 @@ impl Sources {
             })
     }
 
-    pub async fn from_iter<I, Url, Steps>(
+    pub(crate) async fn from_iter<I, Url, Steps>(
         iter: I,
         concurrent_resolve: Option<NonZeroU8>,
     ) -> Result<Self>
     where
-        I: IntoIterator<Item = (Url, Steps)>,
+        I: IntoIterator<Item = (Url, IpFamily, Steps)>,
         Url: AsRef<str>,
         Steps: IntoIterator<Item = ProcessStep>,
     {
@@ -307,10 +321,13 @@ impl Sources {
     }
 }
 
-impl Deserializable for Sources {
-    async fn deserialize(text: &str) -> Result<Self> {
+impl Sources {
+    /// parses what `sources.toml` holds
+    pub async fn from_toml(text: &str) -> Result<Self> {
         #[derive(Deserialize)]
         struct ProcessIntermediate {
+            #[serde(default)]
+            family: IpFamily,
             steps: Vec<ProcessStep>,
         }
 
@@ -336,15 +353,22 @@ impl Deserializable for Sources {
         );
 
         Self::from_try_iter(
-            value
-                .into_iter()
-                .map(|(url, v)| v.try_into::<ProcessIntermediate>().map(|v| (url, v.steps))),
+            value.into_iter().map(|(url, v)| {
+                v.try_into::<ProcessIntermediate>()
+                    .map(|v| (url, v.family, v.steps))
+            }),
             concurrent_resolve,
         )
         .await
     }
 }
 
+impl Deserializable for Sources {
+    async fn deserialize(text: &str) -> Result<Self> {
+        Self::from_toml(text).await
+    }
+}
+
 impl Debug for Sources {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         f.debug_map()
@@ -389,11 +413,19 @@ pub struct IpSource {
 }
 
 impl IpSource {
-    pub async fn resolve_ip(
+    pub fn family(&self) -> IpFamily {
+        self.process.family
+    }
+
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    pub(crate) async fn resolve_ip(
         self,
         client: &RetryingClient,
         cfg: &Config,
-    ) -> Result<Ipv4Addr, GetIpError> {
+    ) -> Result<IpAddr, GetIpError> {
         let bytes = client.get(self.url).send().await?.bytes().await?;
         self.process.run(bytes, cfg).await
     }
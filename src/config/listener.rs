@@ -1,5 +1,6 @@
 use crate::config::ip_source::Sources;
 use crate::config::{deserialize_from_file, CfgInner, Config};
+use crate::err::MessageBoxes;
 use crate::updaters::{Updater, UpdatersManager};
 use crate::{non_zero, util, DdnsContext, UserMessages};
 use anyhow::Result;
@@ -166,7 +167,7 @@ pub async fn load() -> Result<(DdnsContext, UpdatersManager, ConfigStorage)> {
     macro_rules! exists_or_include {
         ($($name: literal),*) => {
             tokio::try_join!($(async {
-                if !util::try_exists(concat!("./config/", $name)).await? {
+                if !util::try_exists(concat!("./config/", $name, ".toml")).await? {
                     tokio::fs::write(concat!("./config/", $name, ".toml"), include_str!(concat!("../../includes/", $name, ".toml"))).await?;
                 }
                 Ok::<_, io::Error>(())
@@ -179,7 +180,7 @@ pub async fn load() -> Result<(DdnsContext, UpdatersManager, ConfigStorage)> {
     let ip_sources = match deserialize_from_file("./config/sources.toml").await {
         Ok(x) => x,
         Err(err) => {
-            UserMessages::new(non_zero!(1))
+            UserMessages::new(non_zero!(1), Arc::new(MessageBoxes))
                 .warning(format!("{err}\n\n\n\n...Using default config..."))
                 .await;
             Sources::default()
@@ -210,7 +211,9 @@ pub async fn load() -> Result<(DdnsContext, UpdatersManager, ConfigStorage)> {
     let cfg_store = Arc::new(ArcSwap::new(Arc::clone(&cfg)));
     let cfg_weak = Arc::downgrade(&cfg_store);
 
-    let ctx = DdnsContext::new(Config(cfg));
+    let ctx = DdnsContext::builder(Config(cfg))
+        .notifier(MessageBoxes)
+        .build();
     let user_messages = ctx.user_messages.clone();
     let mut updater_manager = UpdatersManager::new();
 
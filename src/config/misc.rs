@@ -3,6 +3,7 @@ use crate::config::Deserializable;
 use anyhow::Result;
 use serde::Deserialize;
 use std::num::NonZeroU8;
+use std::str::FromStr;
 use std::time::Duration;
 
 #[derive(Debug, Eq, Ord, PartialOrd, PartialEq, Deserialize)]
@@ -33,6 +34,15 @@ impl RefreshConfig {
     }
 }
 
+impl Default for RefreshConfig {
+    fn default() -> Self {
+        Self {
+            interval: Self::default_interval(),
+            network_detection: Self::default_network_detection(),
+        }
+    }
+}
+
 #[derive(Debug, Eq, Ord, PartialOrd, PartialEq, Deserialize)]
 pub struct GeneralConfig {
     #[serde(default = "GeneralConfig::default_max_errors")]
@@ -50,7 +60,15 @@ impl GeneralConfig {
     }
 }
 
-#[derive(Debug, Eq, Ord, PartialOrd, PartialEq, Deserialize)]
+impl Default for GeneralConfig {
+    fn default() -> Self {
+        Self {
+            max_errors: Self::default_max_errors(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Eq, Ord, PartialOrd, PartialEq, Deserialize)]
 pub struct MiscConfig {
     refresh: RefreshConfig,
     general: GeneralConfig,
@@ -66,8 +84,16 @@ impl MiscConfig {
     }
 }
 
+impl FromStr for MiscConfig {
+    type Err = toml::de::Error;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        toml::de::from_str(text)
+    }
+}
+
 impl Deserializable for MiscConfig {
     async fn deserialize(text: &str) -> Result<Self> {
-        Ok(toml::de::from_str(text)?)
+        Ok(text.parse()?)
     }
 }
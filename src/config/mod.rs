@@ -1,20 +1,24 @@
-use crate::config::api_fields::{Account, ApiFields, Auth, Zone};
-use crate::config::http::HttpConfig;
+use crate::config::api_fields::{
+    Account, ApiFields, DetectConfig, HaConfig, IpList, StaticRecord, VerifyConfig, Zone,
+};
 use crate::config::ip_source::{IpSource, Sources};
-use crate::config::misc::MiscConfig;
-use crate::retrying_client::{RequestBuilder, AUTHORIZATION_EMAIL, AUTHORIZATION_KEY};
-use reqwest::header::AUTHORIZATION;
 use std::num::NonZeroU8;
 use std::path::Path;
 use std::sync::Arc;
+use url::Url;
 
 pub mod api_fields;
+pub mod family;
 mod http;
 pub mod ip_source;
 pub mod listener;
 mod misc;
+mod template;
 mod time;
 
+pub use http::{ClientConfig, HttpConfig, RateLimitConfig};
+pub use misc::{GeneralConfig, MiscConfig, RefreshConfig};
+
 trait Deserializable: Sized {
     async fn deserialize(text: &str) -> anyhow::Result<Self>;
 }
@@ -52,6 +56,17 @@ impl CfgInner {
 pub struct Config(Arc<CfgInner>);
 
 impl Config {
+    /// starts a config built in code instead of read from `./config`,
+    /// everything but the api fields starts out with the defaults
+    pub fn builder(api_fields: ApiFields) -> ConfigBuilder {
+        ConfigBuilder {
+            api_fields,
+            http: HttpConfig::default(),
+            misc: MiscConfig::default(),
+            ip_sources: None,
+        }
+    }
+
     pub fn ip_sources(&self) -> impl Iterator<Item = IpSource> + '_ {
         self.0.ip_sources.sources()
     }
@@ -64,24 +79,90 @@ impl Config {
         &self.0.misc
     }
 
-    pub fn zone(&self) -> &Zone {
-        &self.0.api_fields.zone
+    pub fn api_base(&self) -> &Url {
+        &self.0.api_fields.api_base
+    }
+
+    pub fn zones(&self) -> &[Zone] {
+        &self.0.api_fields.zones
+    }
+
+    pub fn ip_lists(&self) -> &[IpList] {
+        &self.0.api_fields.ip_lists
+    }
+
+    /// the records declared under `[[records]]`
+    pub fn records(&self) -> &[StaticRecord] {
+        &self.0.api_fields.records
+    }
+
+    /// the top level `[account]`, if there is one
+    pub fn account(&self) -> Option<&Arc<Account>> {
+        self.0.api_fields.account.as_ref()
+    }
+
+    /// the account anywhere in the config with this [`Account::fingerprint`],
+    /// for the state files that remember which account wrote something
+    pub(crate) fn account_by_fingerprint(&self, fingerprint: u64) -> Option<&Arc<Account>> {
+        self.zones()
+            .iter()
+            .chain(self.records().iter().map(StaticRecord::zone))
+            .chain(self.ha().map(HaConfig::zone))
+            .map(Zone::shared_account)
+            .chain(self.account())
+            .find(|account| account.fingerprint() == fingerprint)
     }
 
-    pub fn account(&self) -> &Account {
-        &self.0.api_fields.account
+    pub fn verify(&self) -> Option<&VerifyConfig> {
+        self.0.api_fields.verify.as_ref()
+    }
+
+    pub fn detect(&self) -> Option<&DetectConfig> {
+        self.0.api_fields.detect.as_ref()
+    }
+
+    pub fn ha(&self) -> Option<&HaConfig> {
+        self.0.api_fields.ha.as_ref()
     }
 
     pub fn concurrent_resolve(&self) -> NonZeroU8 {
         self.0.ip_sources.concurrent_resolve
     }
+}
 
-    pub fn authorize_request(&self, request: RequestBuilder) -> RequestBuilder {
-        let request = request.header(AUTHORIZATION_EMAIL, self.account().email.clone());
+/// builds a [`Config`] in code, see [`Config::builder`]
+pub struct ConfigBuilder {
+    api_fields: ApiFields,
+    http: HttpConfig,
+    misc: MiscConfig,
+    ip_sources: Option<Sources>,
+}
 
-        match &self.account().auth {
-            Auth::Token(token_header) => request.header(AUTHORIZATION, token_header.clone()),
-            Auth::Key(key_header) => request.header(AUTHORIZATION_KEY, key_header.clone()),
-        }
+impl ConfigBuilder {
+    /// what `http.toml` holds
+    pub fn http(mut self, http: HttpConfig) -> Self {
+        self.http = http;
+        self
+    }
+
+    /// what `misc.toml` holds
+    pub fn misc(mut self, misc: MiscConfig) -> Self {
+        self.misc = misc;
+        self
+    }
+
+    /// what `sources.toml` holds, the built-in sources are used otherwise
+    pub fn ip_sources(mut self, ip_sources: Sources) -> Self {
+        self.ip_sources = Some(ip_sources);
+        self
+    }
+
+    pub fn build(self) -> Config {
+        Config(Arc::new(CfgInner::new(
+            self.api_fields,
+            self.http,
+            self.misc,
+            self.ip_sources.unwrap_or_default(),
+        )))
     }
 }
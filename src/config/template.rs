@@ -0,0 +1,151 @@
+//! placeholders in record names, so one config can be shared by many machines
+
+use crate::config::family::IpFamily;
+use std::io;
+
+/// the machine's hostname, up to the first dot
+fn hostname() -> io::Result<String> {
+    #[cfg(unix)]
+    let name = nix::unistd::gethostname()?
+        .into_string()
+        .map_err(|_| io::Error::other("the hostname isn't valid unicode"))?;
+
+    #[cfg(windows)]
+    let name = std::env::var("COMPUTERNAME").map_err(io::Error::other)?;
+
+    Ok(name.split('.').next().unwrap_or_default().to_owned())
+}
+
+/// the interface the default route goes through
+#[cfg(target_os = "linux")]
+fn default_iface() -> io::Result<String> {
+    // Iface Destination Gateway ..., the default route goes to 00000000
+    let v4 = std::fs::read_to_string("/proc/net/route")?;
+    let found = v4.lines().skip(1).find_map(|line| {
+        let mut fields = line.split_whitespace();
+        let iface = fields.next()?;
+        (fields.next()? == "00000000").then(|| iface.to_owned())
+    });
+    if let Some(iface) = found {
+        return Ok(iface);
+    }
+
+    // destination, prefix length, ..., and the interface last
+    let v6 = std::fs::read_to_string("/proc/net/ipv6_route")?;
+    v6.lines()
+        .find_map(|line| {
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            let (&dest, &prefix, &iface) = (fields.first()?, fields.get(1)?, fields.last()?);
+            (dest.bytes().all(|b| b == b'0') && prefix == "00" && iface != "lo")
+                .then(|| iface.to_owned())
+        })
+        .ok_or_else(|| io::Error::other("there is no default route"))
+}
+
+#[cfg(target_os = "macos")]
+fn default_iface() -> io::Result<String> {
+    let output = std::process::Command::new("route")
+        .args(["-n", "get", "default"])
+        .output()?;
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("interface:"))
+        .map(|iface| iface.trim().to_owned())
+        .ok_or_else(|| io::Error::other("there is no default route"))
+}
+
+#[cfg(target_os = "windows")]
+fn default_iface() -> io::Result<String> {
+    Err(io::Error::other("{iface} isn't supported on windows"))
+}
+
+/// interface names can hold characters that don't belong in a dns label, like the dot in `eth0.100`
+fn to_label(name: &str) -> String {
+    name.chars()
+        .map(|c| match c.is_ascii_alphanumeric() {
+            true => c.to_ascii_lowercase(),
+            false => '-',
+        })
+        .collect()
+}
+
+/// expands `{hostname}`, `{iface}` and `{family}` (`v4` or `v6`),
+/// `family` is only given when the template is split up by family
+pub(super) fn expand(template: &str, family: Option<IpFamily>) -> Result<String, String> {
+    let mut expanded = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        expanded.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| format!("unclosed placeholder in {template}"))?;
+
+        let value = match &rest[start + 1..start + end] {
+            "hostname" => hostname().map_err(|e| format!("unable to read the hostname: {e}"))?,
+            "iface" => default_iface()
+                .map(|iface| to_label(&iface))
+                .map_err(|e| format!("unable to find the default interface: {e}"))?,
+            "family" => match family {
+                Some(IpFamily::V4) => "v4".to_owned(),
+                Some(IpFamily::V6) => "v6".to_owned(),
+                None => return Err("{family} can only be used in the record name".to_owned()),
+            },
+            other => return Err(format!("unknown placeholder {{{other}}} in {template}")),
+        };
+
+        expanded.push_str(&value);
+        rest = &rest[start + end + 1..];
+    }
+
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn leaves_plain_names_alone() {
+        assert_eq!(expand("www.example.com", None).unwrap(), "www.example.com");
+    }
+
+    #[test]
+    fn expands_the_family() {
+        let template = "{family}.home.example.com";
+        assert_eq!(
+            expand(template, Some(IpFamily::V4)).unwrap(),
+            "v4.home.example.com"
+        );
+        assert_eq!(
+            expand(template, Some(IpFamily::V6)).unwrap(),
+            "v6.home.example.com"
+        );
+        assert!(expand(template, None).is_err());
+    }
+
+    #[test]
+    fn expands_the_hostname() {
+        let hostname = hostname().unwrap();
+        assert_eq!(
+            expand("{hostname}-{family}.example.com", Some(IpFamily::V4)).unwrap(),
+            format!("{hostname}-v4.example.com")
+        );
+    }
+
+    #[test]
+    fn rejects_broken_placeholders() {
+        assert!(expand("{host}.example.com", None)
+            .unwrap_err()
+            .contains("unknown placeholder {host}"));
+        assert!(expand("{hostname.example.com", None)
+            .unwrap_err()
+            .contains("unclosed placeholder"));
+    }
+
+    #[test]
+    fn interfaces_become_labels() {
+        assert_eq!(to_label("eth0.100"), "eth0-100");
+        assert_eq!(to_label("Wi-Fi 2"), "wi-fi-2");
+    }
+}
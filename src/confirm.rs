@@ -0,0 +1,106 @@
+use crate::authoritative::serving;
+use crate::config::api_fields::{VerifyConfig, Zone};
+use crate::config::family::IpFamily;
+use crate::{dbg_println, DdnsContext, UserMessages};
+use anyhow::anyhow;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::Instant;
+
+const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// how far an update has made it
+#[derive(Debug)]
+pub(crate) enum Confirmation {
+    /// the api holds the new address, and every nameserver serves it
+    Verified,
+    /// the api holds the new address, but not every nameserver serves it yet,
+    /// they're watched in the background until the timeout
+    Pending,
+    /// the api doesn't hold the new address
+    Failed(anyhow::Error),
+}
+
+impl DdnsContext {
+    /// reads the record back through the api, then asks the authoritative nameservers once,
+    /// the ones lagging behind are watched in the background, so the update loop never waits on them
+    pub(crate) async fn confirm(
+        &self,
+        zone: &Zone,
+        family: IpFamily,
+        ip: IpAddr,
+        cfg: &VerifyConfig,
+    ) -> Confirmation {
+        let inner = async {
+            let records = self.records(zone, family).await?;
+            if !records.iter().any(|record| record.ip == ip) {
+                return Err(anyhow!(
+                    "the api doesn't hold {ip}, it holds {:?}",
+                    records.iter().map(|record| record.ip).collect::<Vec<_>>()
+                ));
+            }
+
+            // proxied records resolve to cloudflare's own addresses, never to ours
+            if zone.proxied() {
+                return Ok(Confirmation::Verified);
+            }
+
+            let nameservers = self.nameservers(zone, cfg.nameservers()).await?;
+            let serving = serving(&nameservers, zone.record(), family, ip).await;
+            if serving == nameservers.len() {
+                return Ok(Confirmation::Verified);
+            }
+
+            dbg_println!(
+                "{serving} of {} nameservers serve {ip} for {}, watching the rest",
+                nameservers.len(),
+                zone.record()
+            );
+            tokio::spawn(watch(
+                nameservers,
+                Box::from(zone.record()),
+                family,
+                ip,
+                cfg.timeout(),
+                self.user_messages.clone(),
+            ));
+            Ok(Confirmation::Pending)
+        };
+
+        inner.await.unwrap_or_else(Confirmation::Failed)
+    }
+}
+
+/// asks the nameservers until all of them serve the address, and warns if the timeout passes first
+async fn watch(
+    nameservers: Arc<[SocketAddr]>,
+    record: Box<str>,
+    family: IpFamily,
+    ip: IpAddr,
+    timeout: Duration,
+    user_messages: UserMessages,
+) {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if Instant::now() + RETRY_INTERVAL > deadline {
+            let serving = serving(&nameservers, &record, family, ip).await;
+            if serving != nameservers.len() {
+                user_messages
+                    .warning(format!(
+                        "only {serving} of {} nameservers serve {ip} for {record} {} seconds after the update",
+                        nameservers.len(),
+                        timeout.as_secs()
+                    ))
+                    .await;
+            }
+            return;
+        }
+
+        tokio::time::sleep(RETRY_INTERVAL).await;
+        if serving(&nameservers, &record, family, ip).await == nameservers.len() {
+            dbg_println!("every nameserver serves {ip} for {record} now");
+            return;
+        }
+    }
+}
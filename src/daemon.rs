@@ -0,0 +1,197 @@
+//! the daemon itself, the `cloudflare-ddns` binary only calls [`run`]
+
+use crate::network_listener::has_internet;
+use crate::pre::RunMode;
+use crate::updaters::{UpdaterEvent, UpdaterExitStatus};
+use crate::util::new_skip_interval;
+use crate::{
+    abort, config, console_listener, dbg_println, err, failover, ha, network_listener, pre, util,
+};
+use anyhow::Result;
+use std::panic::AssertUnwindSafe;
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::thread;
+use std::thread::Builder;
+use std::time::Duration;
+
+enum Action {
+    Restart,
+    Exit(u8),
+}
+
+async fn real_main(mode: &RunMode) -> Result<Action> {
+    let (ctx, mut updaters_manager, cfg_store) = config::listener::load().await?;
+    let ctx = Arc::new(ctx);
+    // a changed api.toml restarts us, so this also runs after every reload
+    ctx.verify_access(&cfg_store.load_config()).await?;
+
+    if let RunMode::Rollback(snapshot) = mode {
+        let (restored, errors) = ctx
+            .rollback(&cfg_store.load_config(), snapshot.as_deref())
+            .await?;
+        updaters_manager.shutdown().await;
+
+        // the process exits right after, so wait for the report to be seen
+        let failed = !errors.is_empty();
+        let _ = tokio::task::spawn_blocking(move || match failed {
+            false => err::info(&format!("restored {restored} records")),
+            true => {
+                let reasons = errors
+                    .iter()
+                    .map(|err| format!("{err:#}"))
+                    .collect::<Vec<_>>();
+                err::error(&format!(
+                    "restored {restored} of {} records\n{}",
+                    restored + reasons.len(),
+                    reasons.join("\n")
+                ))
+            }
+        })
+        .await;
+        return Ok(Action::Exit(u8::from(failed)));
+    }
+    let network_detection = cfg_store.load_config().misc().refresh().network_detection();
+
+    if network_detection {
+        network_listener::subscribe(&mut updaters_manager)?;
+    }
+    err::exit::subscribe(&mut updaters_manager)?;
+    console_listener::subscribe(&mut updaters_manager)?;
+    failover::subscribe(
+        &mut updaters_manager,
+        cfg_store.load_config(),
+        Arc::clone(&ctx.health),
+    )?;
+    ha::subscribe(
+        &mut updaters_manager,
+        cfg_store.load_config(),
+        Arc::clone(&ctx),
+    )?;
+
+    let mut interval = new_skip_interval(cfg_store.load_config().misc().refresh().interval());
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {
+                // the simulation only talks to localhost
+                if *mode != RunMode::Simulate && !has_internet().await {
+                    dbg_println!("no internet available skipping update");
+                    continue;
+                }
+
+                let cfg = cfg_store.load_config();
+                // a standby only watches, the leader publishes
+                if !ctx.is_leader(&cfg) {
+                    match ctx.describe_role() {
+                        Some(role) => dbg_println!("skipping the update as {role}"),
+                        None => dbg_println!("skipping the update until the lease was checked"),
+                    }
+                    continue;
+                }
+
+                match ctx.describe_role() {
+                    Some(role) => dbg_println!("updating as the {role}"),
+                    None => dbg_println!("updating"),
+                }
+                let report = ctx.run_ddns(cfg).await;
+                for err in report.errors {
+                    ctx.user_messages.error(format!("{err:#}")).await
+                }
+                dbg_println!(
+                    "successfully updated {} records, {} records were already up to date",
+                    report.updated,
+                    report.unchanged
+                );
+                if report.held != 0 {
+                    dbg_println!(
+                        "{} records are held back until `cloudflare-ddns acknowledge` is run",
+                        report.held
+                    );
+                }
+                if report.verified + report.pending != 0 {
+                    dbg_println!(
+                        "{} updates are verified, {} are still pending on the nameservers",
+                        report.verified,
+                        report.pending
+                    );
+                }
+            },
+            res = updaters_manager.watch() => match res {
+                UpdaterEvent::Update => interval.reset_immediately(),
+                UpdaterEvent::ServiceEvent(exit) => {
+                    match *exit.status() {
+                        UpdaterExitStatus::Success => {},
+                        UpdaterExitStatus::Panic | UpdaterExitStatus::Error(_) => {
+                            ctx.user_messages.error(format!("Updater abruptly exited: {exit}")).await
+                        }
+                        UpdaterExitStatus::TriggerExit(code) => {
+                            updaters_manager.shutdown().await;
+                            return Ok(Action::Exit(code));
+                        },
+                        UpdaterExitStatus::TriggerRestart => return Ok(Action::Restart),
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "trace")]
+fn make_runtime() -> tokio::runtime::Handle {
+    (*util::GLOBAL_TOKIO_RUNTIME).clone()
+}
+
+#[cfg(not(feature = "trace"))]
+fn make_runtime() -> tokio::runtime::Runtime {
+    tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(util::num_cpus().get())
+        .enable_all()
+        .build()
+        .expect("failed to build runtime")
+}
+
+/// runs the daemon until it's told to exit, restarting it after a config change or a panic,
+/// everything the `cloudflare-ddns` binary does
+pub fn run() -> ExitCode {
+    let mode = pre::pre_run();
+    #[cfg(feature = "trace")]
+    console_subscriber::init();
+
+    let mut runtime = make_runtime();
+    loop {
+        let exit =
+            std::panic::catch_unwind(AssertUnwindSafe(|| runtime.block_on(real_main(&mode))));
+
+        match exit {
+            // Non-Recoverable
+            Ok(Ok(Action::Exit(exit))) => {
+                dbg_println!("Shutting down the runtime...");
+                drop(runtime);
+                dbg_println!("Exiting...");
+                return ExitCode::from(exit);
+            }
+            Ok(Err(e)) => {
+                dbg_println!("Fatal init error");
+                dbg_println!("Aborting...");
+                // best effort clean up
+                let _ = Builder::new().spawn(move || drop(runtime));
+                abort!("{e}")
+            }
+
+            // Recoverable
+            Ok(Ok(Action::Restart)) => dbg_println!("Restarting..."),
+            Err(_) => {
+                // old runtime might be in an invalid state
+                // replace it and drop it on a new thread to avoid hanging
+                let old_runtime = std::mem::replace(&mut runtime, make_runtime());
+                thread::spawn(move || drop(old_runtime));
+
+                dbg_println!("Panicked!!");
+                dbg_println!("Retrying in 15s...");
+                thread::sleep(Duration::from_secs(15));
+                dbg_println!("Retrying")
+            }
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use crate::authoritative::serving;
+use crate::config::api_fields::{DetectConfig, DetectMode, Zone};
+use crate::config::family::IpFamily;
+use crate::state::Published;
+use crate::{dbg_println, DdnsContext};
+use std::net::IpAddr;
+use std::time::Instant;
+
+impl DdnsContext {
+    /// whether this cycle reads every record from the api, which it does every cycle
+    /// without change detection, and otherwise once every reconcile interval
+    pub(crate) fn reconcile_due(&self, cfg: Option<&DetectConfig>) -> bool {
+        let Some(cfg) = cfg.filter(|cfg| cfg.mode() != DetectMode::Api) else {
+            return true;
+        };
+
+        let mut last = self.last_reconcile.lock().unwrap();
+        let due = last.is_none_or(|last| last.elapsed() >= cfg.reconcile());
+        if due {
+            *last = Some(Instant::now());
+        }
+        due
+    }
+
+    /// whether the record already holds the address, found out without calling the api
+    pub(crate) async fn up_to_date(
+        &self,
+        zone: &Zone,
+        family: IpFamily,
+        ip: IpAddr,
+        cfg: &DetectConfig,
+    ) -> bool {
+        let key = Published::key(zone.record(), family.record_type());
+        let cached = self.published.get(&key) == Some(ip);
+
+        // proxied records resolve to cloudflare's own addresses, so only the cache can tell
+        if cfg.mode() == DetectMode::Cache || zone.proxied() {
+            return cached;
+        }
+
+        let nameservers = match self.nameservers(zone, cfg.nameservers()).await {
+            Ok(nameservers) => nameservers,
+            Err(err) => {
+                dbg_println!(
+                    "unable to find the nameservers of {}: {err:#}",
+                    zone.record()
+                );
+                return false;
+            }
+        };
+
+        serving(&nameservers, zone.record(), family, ip).await == nameservers.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::{simulate, tests};
+
+    async fn config(zone: &str, detect: &str) -> Config {
+        let api = format!(
+            r#"
+            [account]
+            api-token = "8dY3nH-As0krmv83n3pm1l"
+
+            [[zone]]
+            record = "home.example.com"
+            family = "v4"
+            {zone}
+
+            [detect]
+            {detect}
+            "#
+        );
+        let (api, _) = simulate::fake_api(&api, |_| false).await.unwrap();
+        Config::builder(api).build()
+    }
+
+    #[tokio::test]
+    async fn reads_the_api_every_cycle_in_api_mode() {
+        let cfg = config("", r#"mode = "api""#).await;
+        let ctx = tests::context(&cfg, "detect-api");
+        assert!(ctx.reconcile_due(cfg.detect()));
+        assert!(ctx.reconcile_due(cfg.detect()));
+        assert!(ctx.reconcile_due(None));
+    }
+
+    #[tokio::test]
+    async fn reconciles_once_per_interval() {
+        let cfg = config("", r#"mode = "dns""#).await;
+        let ctx = tests::context(&cfg, "detect-reconcile");
+        assert!(ctx.reconcile_due(cfg.detect()));
+        assert!(!ctx.reconcile_due(cfg.detect()));
+    }
+
+    #[tokio::test]
+    async fn asks_the_nameservers_in_dns_mode() {
+        let cfg = config("", r#"mode = "dns""#).await;
+        let ctx = tests::context(&cfg, "detect-dns");
+        let (zone, detect) = (&cfg.zones()[0], cfg.detect().unwrap());
+
+        // the fake seeds the record with a stale address
+        let served = ip_macro::ip!("192.0.2.1");
+        assert!(ctx.up_to_date(zone, IpFamily::V4, served, detect).await);
+        let current = ip_macro::ip!("203.0.113.7");
+        assert!(!ctx.up_to_date(zone, IpFamily::V4, current, detect).await);
+    }
+
+    #[tokio::test]
+    async fn only_trusts_the_cache_for_proxied_records() {
+        let cfg = config("proxied = true", r#"mode = "dns""#).await;
+        let ctx = tests::context(&cfg, "detect-proxied");
+        let (zone, detect) = (&cfg.zones()[0], cfg.detect().unwrap());
+
+        let served = ip_macro::ip!("192.0.2.1");
+        assert!(!ctx.up_to_date(zone, IpFamily::V4, served, detect).await);
+        ctx.save_published(zone, IpFamily::V4, served)
+            .await
+            .unwrap();
+        assert!(ctx.up_to_date(zone, IpFamily::V4, served, detect).await);
+    }
+}
@@ -1,5 +1,5 @@
 use std::borrow::Cow;
-use std::panic::PanicInfo;
+use std::panic::PanicHookInfo;
 use std::rc::Rc;
 use std::sync::Arc;
 use std::thread;
@@ -11,7 +11,11 @@ pub mod exit;
 #[macro_export]
 macro_rules! dbg_println {
     ($($arg:tt)*) => {
-        { #[cfg(debug_assertions)] { eprintln!($($arg)*) } }
+        {
+            #[cfg(debug_assertions)] { eprintln!($($arg)*) }
+            // still use the arguments in release, so values only logged aren't unused
+            #[cfg(not(debug_assertions))] { let _ = format_args!($($arg)*); }
+        }
     };
 }
 
@@ -57,7 +61,7 @@ mod sys {
     use std::os::windows::ffi::OsStrExt;
     use windows::core::{w as wide, PCWSTR};
     use windows::Win32::UI::WindowsAndMessaging::{
-        MessageBoxW, MB_ICONERROR, MB_ICONWARNING, MB_OK, MESSAGEBOX_STYLE,
+        MessageBoxW, MB_ICONERROR, MB_ICONINFORMATION, MB_ICONWARNING, MB_OK, MESSAGEBOX_STYLE,
     };
 
     fn encode_wide(str: &OsStr) -> Vec<u16> {
@@ -92,6 +96,11 @@ mod sys {
         // # Safety: caption was made by the wide macro which is valid
         unsafe { present_alert(wide!("CloudFlare DDNS Error"), err.as_ref(), MB_ICONERROR) }
     }
+
+    pub fn info(info: &str) {
+        // # Safety: caption was made by the wide macro which is valid
+        unsafe { present_alert(wide!("CloudFlare DDNS"), info.as_ref(), MB_ICONINFORMATION) }
+    }
 }
 
 #[cfg(target_os = "macos")]
@@ -100,8 +109,8 @@ mod sys {
     use core_foundation::string::CFString;
     use core_foundation_sys::base::CFOptionFlags;
     use core_foundation_sys::user_notification::{
-        kCFUserNotificationCautionAlertLevel, kCFUserNotificationStopAlertLevel,
-        CFUserNotificationDisplayAlert,
+        kCFUserNotificationCautionAlertLevel, kCFUserNotificationNoteAlertLevel,
+        kCFUserNotificationStopAlertLevel, CFUserNotificationDisplayAlert,
     };
 
     fn present_alert(title: &str, message: &str, flags: CFOptionFlags) {
@@ -139,6 +148,10 @@ mod sys {
             kCFUserNotificationStopAlertLevel,
         );
     }
+
+    pub fn info(info: &str) {
+        present_alert("CloudFlare DDNS", info, kCFUserNotificationNoteAlertLevel);
+    }
 }
 
 #[cfg(target_os = "linux")]
@@ -235,6 +248,8 @@ mod sys {
 
                 log::set_logger(LOGGERS.get_or_init(Loggers::default))
                     .expect("unable to set any form of logging");
+                // the log macros drop everything until a level is set
+                log::set_max_level(log::LevelFilter::Info);
                 ErrorBackEnd::Logger
             })
             .clone()
@@ -289,6 +304,7 @@ mod sys {
             ErrorBackEnd::Logger => match message_type {
                 log::Level::Warn => log::warn!("[{title}]: {msg}"),
                 log::Level::Error => log::error!("[{title}]: {msg}"),
+                log::Level::Info => log::info!("[{title}]: {msg}"),
                 _ => unreachable!(),
             },
         }
@@ -301,6 +317,10 @@ mod sys {
     pub fn err(err: &str) {
         present_alert("CloudFlare DDNS Error", err, log::Level::Error);
     }
+
+    pub fn info(info: &str) {
+        present_alert("CloudFlare DDNS", info, log::Level::Info);
+    }
 }
 
 #[cold]
@@ -317,6 +337,27 @@ pub fn warn(warning: &str) {
     sys::warn(warning)
 }
 
+/// the outcome of a subcommand, it has to show up even without a console
+#[cold]
+#[inline(never)]
+pub fn info(info: &str) {
+    dbg_println!("{info}");
+    sys::info(info)
+}
+
+/// how the daemon shows warnings and errors, as message boxes on windows and macos and in the logs on linux
+pub(crate) struct MessageBoxes;
+
+impl crate::Notifier for MessageBoxes {
+    fn error(&self, msg: &str) {
+        error(msg)
+    }
+
+    fn warning(&self, msg: &str) {
+        warn(msg)
+    }
+}
+
 pub async fn spawn_message_box(semaphore: Arc<Semaphore>, err: impl FnOnce() + Send + 'static) {
     if let Ok(permit) = semaphore.acquire_owned().await {
         spawn_thread(move || {
@@ -326,7 +367,7 @@ pub async fn spawn_message_box(semaphore: Arc<Semaphore>, err: impl FnOnce() + S
     }
 }
 
-fn hook(info: &PanicInfo) {
+fn hook(info: &PanicHookInfo) {
     macro_rules! try_cast {
         ([$payload:expr] $type: ty $(, $rest: ty)* |> $default: expr) => {
             match $payload.downcast_ref::<$type>() {
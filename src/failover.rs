@@ -0,0 +1,310 @@
+use crate::config::api_fields::{Candidate, Failover, Probe, Zone};
+use crate::config::family::IpFamily;
+use crate::config::Config;
+use crate::dbg_println;
+use crate::updaters::{Updater, UpdatersManager};
+use crate::util::new_skip_interval;
+use ahash::HashMap;
+use futures::future;
+use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use tokio::net::TcpStream;
+
+/// where a candidate stands, it only flips once enough probes in a row disagree with it
+struct CandidateHealth {
+    healthy: bool,
+    streak: u8,
+}
+
+/// the health of every failover candidate, shared between the probes and the update loop
+#[derive(Default)]
+pub(crate) struct Health {
+    /// the address each record would get without failover, as of the last update
+    dynamic: Mutex<HashMap<(Box<str>, IpFamily), IpAddr>>,
+    candidates: Mutex<HashMap<(Box<str>, IpAddr), CandidateHealth>>,
+}
+
+impl Health {
+    /// the candidate addresses of this family in order of priority,
+    /// the dynamic one is skipped until the update loop resolved it
+    fn addresses(&self, zone: &Zone, failover: &Failover, family: IpFamily) -> Vec<IpAddr> {
+        let dynamic = self.dynamic.lock().unwrap();
+        failover
+            .candidates()
+            .iter()
+            .filter_map(|candidate| match *candidate {
+                Candidate::Dynamic => dynamic.get(&(Box::from(zone.record()), family)).copied(),
+                Candidate::Static(ip) => Some(ip).filter(|ip| IpFamily::of(ip) == family),
+            })
+            .collect()
+    }
+
+    /// the highest priority healthy address, or the first one when none of them are,
+    /// a candidate nobody probed yet counts as healthy
+    fn select(&self, zone: &Zone, addresses: &[IpAddr]) -> Option<IpAddr> {
+        let candidates = self.candidates.lock().unwrap();
+        addresses
+            .iter()
+            .find(|&&ip| {
+                candidates
+                    .get(&(Box::from(zone.record()), ip))
+                    .is_none_or(|health| health.healthy)
+            })
+            .or(addresses.first())
+            .copied()
+    }
+
+    /// records a probe, returns whether the candidate flipped
+    fn observe(&self, zone: &Zone, failover: &Failover, ip: IpAddr, up: bool) -> bool {
+        let mut candidates = self.candidates.lock().unwrap();
+        let health = candidates
+            .entry((Box::from(zone.record()), ip))
+            .or_insert(CandidateHealth {
+                healthy: true,
+                streak: 0,
+            });
+
+        if health.healthy == up {
+            health.streak = 0;
+            return false;
+        }
+
+        health.streak += 1;
+        let needed = match health.healthy {
+            true => failover.fall(),
+            false => failover.rise(),
+        };
+        if health.streak < needed {
+            return false;
+        }
+
+        health.healthy = up;
+        health.streak = 0;
+        true
+    }
+
+    /// forgets the candidates of this record that aren't in use anymore, like an old dynamic address
+    fn retain(&self, zone: &Zone, in_use: &[IpAddr]) {
+        self.candidates
+            .lock()
+            .unwrap()
+            .retain(|(record, ip), _| **record != *zone.record() || in_use.contains(ip));
+    }
+
+    /// the address the record should point at, given the one it would get without failover
+    pub(crate) fn target(
+        &self,
+        zone: &Zone,
+        family: IpFamily,
+        dynamic: Option<IpAddr>,
+    ) -> Option<IpAddr> {
+        let Some(failover) = zone.failover() else {
+            return dynamic;
+        };
+
+        {
+            let key = (Box::from(zone.record()), family);
+            let mut map = self.dynamic.lock().unwrap();
+            match dynamic {
+                Some(ip) => map.insert(key, ip),
+                None => map.remove(&key),
+            };
+        }
+
+        self.select(zone, &self.addresses(zone, failover, family))
+    }
+}
+
+async fn probe(failover: &Failover, ip: IpAddr) -> bool {
+    let check = async {
+        match failover.probe() {
+            Probe::Tcp(port) => TcpStream::connect((ip, *port)).await.is_ok(),
+            Probe::Http(url) => {
+                let host = url.host_str().unwrap_or_default();
+                let port = url.port_or_known_default().unwrap_or(80);
+                // the url's own host is kept for the Host header and tls,
+                // only the connection goes to the candidate
+                let client = reqwest::Client::builder()
+                    .resolve(host, SocketAddr::new(ip, port))
+                    .redirect(reqwest::redirect::Policy::none())
+                    .build();
+                match client {
+                    Ok(client) => client
+                        .get(url.clone())
+                        .send()
+                        .await
+                        .is_ok_and(|res| res.status().is_success()),
+                    Err(_) => false,
+                }
+            }
+        }
+    };
+
+    tokio::time::timeout(failover.timeout(), check)
+        .await
+        .unwrap_or(false)
+}
+
+/// probes the candidates of a zone forever, asking for an update whenever the chosen one changes
+async fn watch(zone: &Zone, failover: &Failover, health: &Health, updater: &Updater) {
+    let mut timer = new_skip_interval(failover.interval());
+    loop {
+        timer.tick().await;
+
+        // the update loop may learn a new dynamic address meanwhile,
+        // it gets probed next time, this round compares the same addresses before and after
+        let by_family = zone
+            .family()
+            .iter()
+            .map(|family| health.addresses(zone, failover, family))
+            .collect::<Vec<_>>();
+        let before = by_family
+            .iter()
+            .map(|addresses| health.select(zone, addresses))
+            .collect::<Vec<_>>();
+
+        let addresses = by_family.concat();
+        health.retain(zone, &addresses);
+
+        let results = future::join_all(
+            addresses
+                .iter()
+                .map(|&ip| async move { (ip, probe(failover, ip).await) }),
+        )
+        .await;
+
+        for (ip, up) in results {
+            if health.observe(zone, failover, ip, up) {
+                let state = if up { "healthy" } else { "down" };
+                dbg_println!("failover: {ip} of {} is {state}", zone.record());
+            }
+        }
+
+        let after = by_family
+            .iter()
+            .map(|addresses| health.select(zone, addresses));
+        if before.into_iter().ne(after) {
+            dbg_println!("failover: switching {} to another candidate", zone.record());
+            if updater.update().is_err() {
+                return;
+            }
+        }
+    }
+}
+
+pub(crate) fn subscribe(
+    updaters_manager: &mut UpdatersManager,
+    cfg: Config,
+    health: Arc<Health>,
+) -> Result<(), Infallible> {
+    if cfg.zones().iter().all(|zone| zone.failover().is_none()) {
+        return Ok(());
+    }
+
+    let (updater, jh_entry) = updaters_manager.add_updater("failover");
+    jh_entry.insert(tokio::spawn(async move {
+        let watchers = cfg.zones().iter().filter_map(|zone| {
+            let failover = zone.failover()?;
+            Some(watch(zone, failover, &health, &updater))
+        });
+
+        tokio::select! {
+            _ = future::join_all(watchers) => (),
+            _ = updater.wait_shutdown() => (),
+        }
+    }));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::api_fields::ApiFields;
+
+    const PRIMARY: IpAddr = ip_macro::ip!("203.0.113.7");
+    const BACKUP: IpAddr = ip_macro::ip!("198.51.100.20");
+
+    fn zone() -> Zone {
+        let api: ApiFields = r#"
+            [account]
+            api-token = "8dY3nH-As0krmv83n3pm1l"
+
+            [[zone]]
+            record = "home.example.com"
+            family = "v4"
+
+            [zone.failover]
+            candidates = ["dynamic", "198.51.100.20"]
+            probe = { tcp = 443 }
+            rise = 3
+            fall = 2
+            "#
+        .parse()
+        .unwrap();
+        api.zones.into_vec().remove(0)
+    }
+
+    /// feeds the probe results of the primary, and returns where the record points after each of them
+    fn probe_primary(health: &Health, zone: &Zone, results: &[bool]) -> Vec<IpAddr> {
+        let failover = zone.failover().unwrap();
+        results
+            .iter()
+            .map(|&up| {
+                health.observe(zone, failover, PRIMARY, up);
+                health.target(zone, IpFamily::V4, Some(PRIMARY)).unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn starts_on_the_first_candidate() {
+        let (health, zone) = (Health::default(), zone());
+        assert_eq!(
+            health.target(&zone, IpFamily::V4, Some(PRIMARY)),
+            Some(PRIMARY)
+        );
+        // until the dynamic address is known the backup is all there is
+        assert_eq!(health.target(&zone, IpFamily::V4, None), Some(BACKUP));
+    }
+
+    #[test]
+    fn falls_over_after_enough_failed_probes() {
+        let (health, zone) = (Health::default(), zone());
+        health.target(&zone, IpFamily::V4, Some(PRIMARY));
+
+        // a single failure in between passing probes doesn't count
+        assert_eq!(
+            probe_primary(&health, &zone, &[false, true, false, false]),
+            [PRIMARY, PRIMARY, PRIMARY, BACKUP]
+        );
+    }
+
+    #[test]
+    fn comes_back_after_enough_passing_probes() {
+        let (health, zone) = (Health::default(), zone());
+        health.target(&zone, IpFamily::V4, Some(PRIMARY));
+        probe_primary(&health, &zone, &[false, false]);
+
+        assert_eq!(
+            probe_primary(&health, &zone, &[true, true, false, true, true, true]),
+            [BACKUP, BACKUP, BACKUP, BACKUP, BACKUP, PRIMARY]
+        );
+    }
+
+    #[test]
+    fn sticks_to_the_first_candidate_when_all_are_down() {
+        let (health, zone) = (Health::default(), zone());
+        let failover = zone.failover().unwrap();
+        health.target(&zone, IpFamily::V4, Some(PRIMARY));
+        for ip in [PRIMARY, BACKUP] {
+            for _ in 0..failover.fall() {
+                health.observe(&zone, failover, ip, false);
+            }
+        }
+        assert_eq!(
+            health.target(&zone, IpFamily::V4, Some(PRIMARY)),
+            Some(PRIMARY)
+        );
+    }
+}
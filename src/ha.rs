@@ -0,0 +1,324 @@
+use crate::cloudflare::{DnsRecord, RecordBody, RecordFilter};
+use crate::config::api_fields::HaConfig;
+use crate::config::Config;
+use crate::state::ROLE_FILE;
+use crate::updaters::UpdatersManager;
+use crate::util::new_skip_interval;
+use crate::{dbg_println, err, state, DdnsContext};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::convert::Infallible;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// how long a new lease is left to settle before reading it back,
+/// so two instances taking it at once both see who won
+const SETTLE: Duration = Duration::from_secs(2);
+
+/// what this instance is doing, as of the last look at the lease
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", tag = "role")]
+pub(crate) enum Role {
+    /// we hold the lease, and publish
+    Leader { until: u64 },
+    /// someone else holds the lease, or we couldn't tell who does
+    Standby {
+        leader: Option<Box<str>>,
+        until: u64,
+    },
+}
+
+/// the role along with who we are, as written for the `status` subcommand
+#[derive(Debug, Serialize, Deserialize)]
+struct Status {
+    instance: Box<str>,
+    lease: Box<str>,
+    checked: u64,
+    #[serde(flatten)]
+    role: Role,
+}
+
+/// the lease as written in the TXT record
+struct LeaseRecord {
+    id: Box<str>,
+    created_on: Option<Box<str>>,
+    owner: Box<str>,
+    expires: u64,
+}
+
+impl LeaseRecord {
+    fn parse(record: DnsRecord) -> Option<Self> {
+        let mut owner = None;
+        let mut expires = None;
+        for field in record.content.trim_matches('"').split_whitespace() {
+            match field.split_once('=') {
+                Some(("owner", value)) => owner = Some(Box::from(value)),
+                Some(("expires", value)) => expires = value.parse().ok(),
+                _ => {}
+            }
+        }
+
+        Some(LeaseRecord {
+            id: record.id,
+            created_on: record.created_on,
+            owner: owner?,
+            expires: expires?,
+        })
+    }
+
+    /// orders leases by when they were created, the seconds compare as text
+    /// and so does the fraction after them, whatever number of digits cloudflare gives it
+    fn age_key(&self) -> (&str, &str, &str) {
+        let created_on = self.created_on.as_deref().unwrap_or_default();
+        let (secs, fraction) = created_on.split_at_checked(19).unwrap_or((created_on, ""));
+        let fraction = fraction.trim_start_matches('.').trim_end_matches('Z');
+        (secs, fraction, &self.id)
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+impl DdnsContext {
+    /// every lease record under the name, the oldest first,
+    /// records created in the same instant are ordered by their id so every instance agrees
+    async fn read_leases(&self, ha: &HaConfig, zone_id: &str) -> Result<Vec<LeaseRecord>> {
+        let filter = RecordFilter {
+            record_type: Some("TXT"),
+            name: Some(ha.record()),
+        };
+        let mut leases = self
+            .cloudflare
+            .list_dns_records::<DnsRecord>(ha.zone().account(), zone_id, filter)
+            .await?
+            .into_iter()
+            .filter(|record| &*record.name == ha.record())
+            .filter_map(LeaseRecord::parse)
+            .collect::<Vec<_>>();
+        leases.sort_by(|a, b| a.age_key().cmp(&b.age_key()));
+        Ok(leases)
+    }
+
+    /// takes or renews the lease if it's free or ours, otherwise reports who holds it
+    ///
+    /// two instances can both create the lease when there is none,
+    /// the oldest record is the lease then, and each instance deletes its own extra ones
+    async fn contend(&self, ha: &HaConfig) -> Result<Role> {
+        let zone = ha.zone();
+        let zone_id = self.zone_id(zone).await?;
+        let leases = self.read_leases(ha, &zone_id).await?;
+        let now = unix_now();
+
+        for extra in leases.iter().skip(1) {
+            if *extra.owner == *ha.instance() {
+                self.cloudflare
+                    .delete_dns_record(zone.account(), &zone_id, &extra.id)
+                    .await
+                    .context("unable to delete our extra lease")?;
+            }
+        }
+
+        let current = leases.first();
+        if let Some(lease) = current {
+            if *lease.owner != *ha.instance() && lease.expires > now {
+                return Ok(Role::Standby {
+                    leader: Some(lease.owner.clone()),
+                    until: lease.expires,
+                });
+            }
+        }
+
+        let until = now + ha.lease().as_secs();
+        let content = format!("\"owner={} expires={until}\"", ha.instance());
+        let body = RecordBody {
+            record_type: "TXT",
+            name: ha.record(),
+            content: Some(Cow::Borrowed(&content)),
+            ..RecordBody::default()
+        };
+        match current {
+            Some(lease) => {
+                self.cloudflare
+                    .patch_dns_record(zone.account(), &zone_id, &lease.id, &body)
+                    .await?;
+            }
+            None => {
+                self.cloudflare
+                    .create_dns_record(zone.account(), &zone_id, &body)
+                    .await
+                    .context("unable to create the lease record")?;
+            }
+        }
+
+        // renewing our own lease can't race anyone, taking it over can, the last write wins
+        if current.is_none_or(|lease| *lease.owner != *ha.instance()) {
+            tokio::time::sleep(SETTLE).await;
+            match self.read_leases(ha, &zone_id).await?.into_iter().next() {
+                Some(lease) if *lease.owner == *ha.instance() => {}
+                lease => {
+                    return Ok(Role::Standby {
+                        leader: lease.as_ref().map(|lease| lease.owner.clone()),
+                        until: lease.map_or(0, |lease| lease.expires),
+                    })
+                }
+            }
+        }
+
+        Ok(Role::Leader { until })
+    }
+
+    /// whether this instance should publish, always when high availability is off
+    pub(crate) fn is_leader(&self, cfg: &Config) -> bool {
+        cfg.ha().is_none() || matches!(*self.role.lock().unwrap(), Some(Role::Leader { .. }))
+    }
+
+    /// a short description of the role, for the logs
+    pub(crate) fn describe_role(&self) -> Option<String> {
+        Some(match self.role.lock().unwrap().as_ref()? {
+            Role::Leader { .. } => "leader".to_owned(),
+            Role::Standby {
+                leader: Some(leader),
+                ..
+            } => format!("standby, {leader} leads"),
+            Role::Standby { leader: None, .. } => "standby".to_owned(),
+        })
+    }
+
+    /// looks at the lease and stores the new role, returns whether we just became the leader
+    async fn update_role(&self, ha: &HaConfig) -> bool {
+        let now = unix_now();
+        let role = match self.contend(ha).await {
+            Ok(role) => role,
+            Err(err) => {
+                dbg_println!("unable to renew the lease {}: {err:#}", ha.record());
+                // nobody can take the lease before what we last wrote runs out
+                match self.role.lock().unwrap().clone() {
+                    Some(Role::Leader { until }) if until > now => Role::Leader { until },
+                    _ => Role::Standby {
+                        leader: None,
+                        until: 0,
+                    },
+                }
+            }
+        };
+
+        let status = Status {
+            instance: Box::from(ha.instance()),
+            lease: Box::from(ha.record()),
+            checked: now,
+            role: role.clone(),
+        };
+        if let Err(err) = state::write_json(&self.state_dir.join(ROLE_FILE), &status).await {
+            dbg_println!("unable to write {ROLE_FILE}: {err:#}");
+        }
+
+        let was_leader = matches!(
+            self.role.lock().unwrap().replace(role.clone()),
+            Some(Role::Leader { .. })
+        );
+        let is_leader = matches!(role, Role::Leader { .. });
+        if was_leader != is_leader {
+            dbg_println!("now {}", self.describe_role().unwrap_or_default());
+        }
+        is_leader && !was_leader
+    }
+}
+
+/// shows the role the daemon last wrote, for the `status` subcommand
+pub fn show_status(state_dir: &Path) -> Result<()> {
+    let status = match std::fs::read(state_dir.join(ROLE_FILE)) {
+        Ok(bytes) => serde_json::from_slice::<Status>(&bytes).context("corrupt status file")?,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            err::info("no role yet, [ha] is off or the daemon didn't look at the lease yet");
+            return Ok(());
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    let role = match status.role {
+        Role::Leader { until } => format!(
+            "{}: leader, holding {} until {until}",
+            status.instance, status.lease
+        ),
+        Role::Standby {
+            leader: Some(leader),
+            until,
+        } => format!(
+            "{}: standby, {leader} holds {} until {until}",
+            status.instance, status.lease
+        ),
+        Role::Standby { leader: None, .. } => format!(
+            "{}: standby, unable to read the lease {}",
+            status.instance, status.lease
+        ),
+    };
+    let ago = unix_now().saturating_sub(status.checked);
+    err::info(&format!("{role}\nchecked {ago} seconds ago"));
+    Ok(())
+}
+
+/// renews or contends for the lease three times per lease, and asks for an update right away
+/// when we become the leader, so a standby takes over as soon as the old lease runs out
+pub(crate) fn subscribe(
+    updaters_manager: &mut UpdatersManager,
+    cfg: Config,
+    ctx: Arc<DdnsContext>,
+) -> Result<(), Infallible> {
+    if cfg.ha().is_none() {
+        return Ok(());
+    }
+
+    let (updater, jh_entry) = updaters_manager.add_updater("ha-lease");
+    jh_entry.insert(tokio::spawn(async move {
+        let Some(ha) = cfg.ha() else { return };
+        let contend = async {
+            let mut timer = new_skip_interval(ha.lease() / 3);
+            loop {
+                timer.tick().await;
+                if ctx.update_role(ha).await && updater.update().is_err() {
+                    return;
+                }
+            }
+        };
+
+        tokio::select! {
+            _ = contend => (),
+            _ = updater.wait_shutdown() => (),
+        }
+    }));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lease(id: &str, created_on: &str) -> LeaseRecord {
+        LeaseRecord {
+            id: Box::from(id),
+            created_on: Some(Box::from(created_on)),
+            owner: Box::from("peer"),
+            expires: 0,
+        }
+    }
+
+    #[test]
+    fn leases_are_ordered_by_age_then_id() {
+        let mut leases = [
+            lease("c", "2024-05-01T10:00:00Z"),
+            lease("a", "2024-05-01T10:00:00.5Z"),
+            lease("b", "2024-05-01T10:00:00.25Z"),
+            lease("0", "2024-05-01T10:00:01Z"),
+            lease("d", "2024-05-01T10:00:00.25Z"),
+        ];
+        leases.sort_by(|a, b| a.age_key().cmp(&b.age_key()));
+        let ids = leases.iter().map(|lease| &*lease.id).collect::<Vec<_>>();
+        assert_eq!(ids, ["c", "b", "d", "a", "0"]);
+    }
+}
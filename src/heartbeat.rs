@@ -0,0 +1,185 @@
+use crate::cloudflare::{DnsRecord, RecordBody, RecordFilter};
+use crate::config::api_fields::{Heartbeat, Zone};
+use crate::config::family::IpFamily;
+use crate::{dbg_println, DdnsContext};
+use anyhow::{Context, Result};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::time::Instant;
+use url::Url;
+
+/// the last heartbeat written this session
+pub(crate) struct LastBeat {
+    written: Instant,
+    addresses: String,
+}
+
+fn is_heartbeat(record: &DnsRecord) -> bool {
+    record.content.trim_start_matches('"').starts_with("ts=")
+}
+
+impl DdnsContext {
+    /// writes the heartbeat TXT record, with the time of this check, the addresses,
+    /// the sources that answered, and our version
+    ///
+    /// writes are skipped until the interval passes, unless the addresses changed
+    pub(crate) async fn heartbeat(
+        &self,
+        zone: &Zone,
+        heartbeat: &Heartbeat,
+        resolved: &BTreeMap<IpFamily, (IpAddr, Url)>,
+    ) -> Result<()> {
+        let addresses = resolved
+            .iter()
+            .filter(|(&family, _)| zone.family().contains(family))
+            .map(|(family, (ip, source))| {
+                let n = match family {
+                    IpFamily::V4 => 4,
+                    IpFamily::V6 => 6,
+                };
+                format!(
+                    "ipv{n}={ip} src{n}={}",
+                    source.host_str().unwrap_or(source.as_str())
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        if let Some(last) = self.heartbeats.lock().unwrap().get(heartbeat.name()) {
+            if last.addresses == addresses && last.written.elapsed() < heartbeat.interval() {
+                return Ok(());
+            }
+        }
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let content = format!(
+            "\"ts={timestamp} {addresses} version={}\"",
+            env!("CARGO_PKG_VERSION")
+        );
+
+        let zone_id = self.zone_id(zone).await?;
+        let filter = RecordFilter {
+            record_type: Some("TXT"),
+            name: Some(heartbeat.name()),
+        };
+        let existing = self
+            .cloudflare
+            .list_dns_records::<DnsRecord>(zone.account(), &zone_id, filter)
+            .await?
+            .into_iter()
+            .find(|record| &*record.name == heartbeat.name() && is_heartbeat(record));
+
+        let body = RecordBody {
+            record_type: "TXT",
+            name: heartbeat.name(),
+            content: Some(Cow::Borrowed(&content)),
+            ..RecordBody::default()
+        };
+
+        match existing {
+            Some(record) => {
+                self.cloudflare
+                    .patch_dns_record(zone.account(), &zone_id, &record.id, &body)
+                    .await?;
+            }
+            None => {
+                self.cloudflare
+                    .create_dns_record(zone.account(), &zone_id, &body)
+                    .await
+                    .context("unable to create the heartbeat record")?;
+            }
+        }
+
+        dbg_println!("heartbeat {}: {content}", heartbeat.name());
+        self.heartbeats.lock().unwrap().insert(
+            Box::from(heartbeat.name()),
+            LastBeat {
+                written: Instant::now(),
+                addresses,
+            },
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::{simulate, tests};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    fn resolved(ip: IpAddr) -> BTreeMap<IpFamily, (IpAddr, Url)> {
+        let source = Url::parse("https://ipv4.icanhazip.com/").unwrap();
+        BTreeMap::from([(IpFamily::of(&ip), (ip, source))])
+    }
+
+    #[tokio::test]
+    async fn writes_again_once_the_address_changes() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let (api, fake) = simulate::fake_api(
+            r#"
+            [account]
+            api-token = "8dY3nH-As0krmv83n3pm1l"
+
+            [[zone]]
+            record = "home.example.com"
+            family = "v4"
+            heartbeat = true
+            "#,
+            {
+                let requests = Arc::clone(&requests);
+                move |path| {
+                    if path.contains("/dns_records") {
+                        requests.fetch_add(1, Ordering::Relaxed);
+                    }
+                    false
+                }
+            },
+        )
+        .await
+        .unwrap();
+        let cfg = Config::builder(api).build();
+        let ctx = tests::context(&cfg, "heartbeat");
+        let zone = &cfg.zones()[0];
+        let heartbeat = zone.heartbeat().unwrap();
+
+        ctx.heartbeat(zone, heartbeat, &resolved(ip_macro::ip!("203.0.113.7")))
+            .await
+            .unwrap();
+        let written = requests.load(Ordering::Relaxed);
+        assert!(written > 0);
+
+        // nothing changed and the interval hasn't passed
+        ctx.heartbeat(zone, heartbeat, &resolved(ip_macro::ip!("203.0.113.7")))
+            .await
+            .unwrap();
+        assert_eq!(requests.load(Ordering::Relaxed), written);
+
+        ctx.heartbeat(zone, heartbeat, &resolved(ip_macro::ip!("203.0.113.8")))
+            .await
+            .unwrap();
+        assert!(requests.load(Ordering::Relaxed) > written);
+
+        match &*fake.contents("_ddns.home.example.com", "TXT") {
+            [content] => {
+                assert!(content.starts_with("\"ts="), "{content}");
+                assert!(
+                    content.contains(" ipv4=203.0.113.8 src4=ipv4.icanhazip.com "),
+                    "{content}"
+                );
+                assert!(
+                    content.ends_with(&format!(" version={}\"", env!("CARGO_PKG_VERSION"))),
+                    "{content}"
+                );
+            }
+            contents => panic!("expected a single heartbeat, got {contents:?}"),
+        }
+    }
+}
@@ -0,0 +1,223 @@
+use crate::cloudflare::{ListItem, NewListItem};
+use crate::config::api_fields::IpList;
+use crate::config::family::IpFamily;
+use crate::state::Published;
+use crate::{dbg_println, DdnsContext};
+use anyhow::{anyhow, Context, Result};
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+const OPERATION_POLL_INTERVAL: Duration = Duration::from_secs(1);
+const OPERATION_POLL_ATTEMPTS: u32 = 30;
+
+fn item_ip(item: &ListItem) -> Option<IpAddr> {
+    item.ip.as_deref()?.parse().ok()
+}
+
+/// where the last address we put in the list is remembered
+pub(crate) fn list_key(list: &IpList, family: IpFamily) -> Box<str> {
+    Published::key(
+        &format!("ip-list:{}/{}", list.account_id(), list.name()),
+        family.record_type(),
+    )
+}
+
+impl DdnsContext {
+    async fn lookup_list_id(&self, list: &IpList) -> Result<Box<str>> {
+        let lists = self
+            .cloudflare
+            .list_lists(list.account(), list.account_id())
+            .await?;
+
+        let info = lists
+            .into_iter()
+            .find(|info| &*info.name == list.name())
+            .ok_or_else(|| {
+                anyhow!(
+                    "the account {} has no list named {}",
+                    list.account_id(),
+                    list.name()
+                )
+            })?;
+
+        anyhow::ensure!(
+            &*info.kind == "ip",
+            "the list {} holds {} items, not ips",
+            list.name(),
+            info.kind
+        );
+        Ok(info.id)
+    }
+
+    pub(crate) async fn list_id(&self, list: &IpList) -> Result<Arc<str>> {
+        let key = (Box::from(list.account_id()), Box::from(list.name()));
+        if let Some(id) = self.list_ids.lock().unwrap().get(&key) {
+            return Ok(Arc::clone(id));
+        }
+
+        let id = Arc::<str>::from(self.lookup_list_id(list).await?);
+        self.list_ids.lock().unwrap().insert(key, Arc::clone(&id));
+        Ok(id)
+    }
+
+    /// list changes go through in the background, this waits for one to be applied
+    async fn wait_for_operation(&self, list: &IpList, operation_id: &str) -> Result<()> {
+        for _ in 0..OPERATION_POLL_ATTEMPTS {
+            let operation = self
+                .cloudflare
+                .get_bulk_operation(list.account(), list.account_id(), operation_id)
+                .await?;
+
+            match &*operation.status {
+                "completed" => return Ok(()),
+                "failed" => anyhow::bail!(
+                    "cloudflare failed to update the list: {}",
+                    operation.error.as_deref().unwrap_or("no details given")
+                ),
+                _ => tokio::time::sleep(OPERATION_POLL_INTERVAL).await,
+            }
+        }
+
+        anyhow::bail!(
+            "the list update is still pending after {}s",
+            (OPERATION_POLL_INTERVAL * OPERATION_POLL_ATTEMPTS).as_secs()
+        )
+    }
+
+    /// puts the current address in the list in place of our old one, leaving every other item alone
+    ///
+    /// our items are the ones with the configured comment, or the one holding the last address we published
+    pub(crate) async fn sync_ip_list(
+        &self,
+        list: &IpList,
+        family: IpFamily,
+        current_ip: IpAddr,
+    ) -> Result<bool> {
+        let list_id = self.list_id(list).await?;
+        let items = self
+            .cloudflare
+            .list_list_items(list.account(), list.account_id(), &list_id)
+            .await?;
+
+        let key = list_key(list, family);
+        let published = self.published.get(&key);
+
+        let present = items.iter().any(|item| item_ip(item) == Some(current_ip));
+        let stale = items
+            .iter()
+            .filter(|item| {
+                let Some(ip) = item_ip(item) else {
+                    return false;
+                };
+                let ours = list
+                    .comment()
+                    .is_some_and(|comment| item.comment.as_deref() == Some(comment))
+                    || Some(ip) == published;
+
+                ours && IpFamily::of(&ip) == family && ip != current_ip
+            })
+            .map(|item| &*item.id)
+            .collect::<Vec<_>>();
+
+        // add before removing, so whatever the list guards never goes without our address
+        if !present {
+            let new_item = NewListItem {
+                ip: current_ip.to_string(),
+                comment: list.comment(),
+            };
+            let operation = self
+                .cloudflare
+                .add_list_items(list.account(), list.account_id(), &list_id, &[new_item])
+                .await?;
+            self.wait_for_operation(list, &operation.operation_id)
+                .await?;
+            dbg_println!("added {current_ip} to the ip list {}", list.name());
+        }
+
+        if !stale.is_empty() {
+            let operation = self
+                .cloudflare
+                .delete_list_items(list.account(), list.account_id(), &list_id, &stale)
+                .await?;
+            self.wait_for_operation(list, &operation.operation_id)
+                .await?;
+            dbg_println!(
+                "removed {} old entries from the ip list {}",
+                stale.len(),
+                list.name()
+            );
+        }
+
+        self.published
+            .set(key, current_ip)
+            .await
+            .context("unable to save the published address")?;
+
+        Ok(!present || !stale.is_empty())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::{simulate, tests};
+
+    async fn sync(api: &str, test: &str, addresses: &[IpAddr]) -> Vec<IpAddr> {
+        let (api, fake) = simulate::fake_api(api, |_| false).await.unwrap();
+        let cfg = Config::builder(api).build();
+        let ctx = tests::context(&cfg, test);
+
+        let list = &cfg.ip_lists()[0];
+        for &ip in addresses {
+            ctx.sync_ip_list(list, IpFamily::V4, ip).await.unwrap();
+        }
+        fake.list_items(list.name())
+    }
+
+    #[tokio::test]
+    async fn replaces_the_item_with_our_comment() {
+        let items = sync(
+            r#"
+            [account]
+            api-token = "8dY3nH-As0krmv83n3pm1l"
+
+            [[ip-list]]
+            account-id = "0123456789abcdef"
+            name = "offices"
+            family = "v4"
+            comment = "home"
+            "#,
+            "ip-list-comment",
+            &[ip_macro::ip!("203.0.113.7")],
+        )
+        .await;
+        assert_eq!(
+            items,
+            [ip_macro::ip!("198.51.100.10"), ip_macro::ip!("203.0.113.7")]
+        );
+    }
+
+    #[tokio::test]
+    async fn replaces_the_address_we_published_last() {
+        let items = sync(
+            r#"
+            [account]
+            api-token = "8dY3nH-As0krmv83n3pm1l"
+
+            [[ip-list]]
+            account-id = "0123456789abcdef"
+            name = "offices"
+            family = "v4"
+            "#,
+            "ip-list-published",
+            &[ip_macro::ip!("203.0.113.7"), ip_macro::ip!("203.0.113.8")],
+        )
+        .await;
+        assert_eq!(
+            items,
+            [ip_macro::ip!("198.51.100.10"), ip_macro::ip!("203.0.113.8")]
+        );
+    }
+}
@@ -0,0 +1,85 @@
+use crate::config::api_fields::{LanHost, PrefixSource};
+use std::io;
+use std::net::{IpAddr, Ipv6Addr};
+
+/// keeps the first `prefix_length` bits of the prefix, and takes the rest from the suffix
+fn combine(prefix: Ipv6Addr, suffix: Ipv6Addr, prefix_length: u8) -> Ipv6Addr {
+    let mask = u128::MAX << (128 - u32::from(prefix_length));
+    Ipv6Addr::from((u128::from(prefix) & mask) | (u128::from(suffix) & !mask))
+}
+
+fn is_global(ip: &Ipv6Addr) -> bool {
+    // 2000::/3, everything else is link local, unique local, multicast or reserved
+    ip.segments()[0] & 0xe000 == 0x2000
+}
+
+/// the first global address on the interface
+#[cfg(unix)]
+fn interface_prefix(iface: &str) -> io::Result<Ipv6Addr> {
+    nix::ifaddrs::getifaddrs()?
+        .filter(|addr| addr.interface_name == iface)
+        .filter_map(|addr| Some(addr.address?.as_sockaddr_in6()?.ip()))
+        .find(is_global)
+        .ok_or_else(|| io::Error::other(format!("{iface} has no global ipv6 address")))
+}
+
+#[cfg(windows)]
+fn interface_prefix(_: &str) -> io::Result<Ipv6Addr> {
+    Err(io::Error::other(
+        "prefix-from an interface isn't supported on windows",
+    ))
+}
+
+/// the address of the host with the current prefix, `None` while the ip sources have no ipv6 address
+pub(crate) fn host_address(host: &LanHost, resolved: Option<IpAddr>) -> io::Result<Option<IpAddr>> {
+    let prefix = match host.prefix_from() {
+        PrefixSource::IpSource => match resolved {
+            Some(IpAddr::V6(ip)) => ip,
+            _ => return Ok(None),
+        },
+        PrefixSource::Iface(iface) => interface_prefix(iface)?,
+    };
+
+    let ip = combine(prefix, host.suffix(), host.prefix_length());
+    Ok(Some(IpAddr::V6(ip)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(ip: &str) -> Ipv6Addr {
+        ip.parse().unwrap()
+    }
+
+    #[test]
+    fn takes_the_prefix_from_the_prefix_and_the_rest_from_the_suffix() {
+        assert_eq!(
+            combine(ip("2001:db8:1:2:aaaa::1"), ip("::1:2:3:4"), 64),
+            ip("2001:db8:1:2:1:2:3:4")
+        );
+        assert_eq!(
+            combine(ip("2001:db8:1:2::"), ip("::ff:1:2:3:4"), 56),
+            ip("2001:db8:1:ff:1:2:3:4")
+        );
+    }
+
+    #[test]
+    fn works_at_the_edges() {
+        let (prefix, suffix) = (ip("2001:db8::"), ip("::1"));
+        assert_eq!(combine(prefix, suffix, 1), ip("::1"));
+        assert_eq!(combine(prefix, suffix, 127), ip("2001:db8::1"));
+        let ones = Ipv6Addr::from(u128::MAX);
+        assert_eq!(combine(ones, Ipv6Addr::UNSPECIFIED, 1), ip("8000::"));
+        assert_eq!(combine(Ipv6Addr::UNSPECIFIED, ones, 127), ip("::1"));
+    }
+
+    #[test]
+    fn only_2000_slash_3_is_global() {
+        assert!(is_global(&ip("2001:db8::1")));
+        assert!(is_global(&ip("3fff::1")));
+        assert!(!is_global(&ip("fe80::1")));
+        assert!(!is_global(&ip("fd00::1")));
+        assert!(!is_global(&ip("::1")));
+    }
+}
@@ -0,0 +1,1533 @@
+//! The engine behind the `cloudflare-ddns` daemon, for tools that want to resolve the public ip
+//! and keep records up to date without running the daemon itself.
+//!
+//! ```no_run
+//! # async fn example() -> anyhow::Result<()> {
+//! use cloudflare_ddns::{ApiFields, Config, DdnsContext, IpFamily};
+//!
+//! let api_fields: ApiFields = r#"
+//!     [account]
+//!     api-token = "8dY3nH-As0krmv83n3pm1l"
+//!
+//!     [[zone]]
+//!     record = "home.example.com"
+//! "#
+//! .parse()?;
+//! let cfg = Config::builder(api_fields).build();
+//!
+//! let ctx = DdnsContext::new(cfg.clone());
+//! let (ip, source) = ctx.get_ip(IpFamily::V4, &cfg).await?;
+//! println!("{ip} according to {source}");
+//!
+//! let zone = &cfg.zones()[0];
+//! for record in ctx.records(zone, IpFamily::V4).await? {
+//!     println!("{} {} holds {}", zone.record(), record.id(), record.ip());
+//! }
+//! ctx.update_record(zone, ip).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Like the daemon, the addresses we published and the records we created are kept in `./state`,
+//! [`DdnsContext::builder`] can keep them somewhere else, and send the warnings somewhere other than stderr.
+
+use crate::cloudflare::{
+    ApiError, BatchBody, BatchDelete, BatchPatch, DnsRecord, RecordBody, RecordFilter, SvcbData,
+    ZoneInfo,
+};
+use crate::config::ip_source::GetIpError;
+use crate::confirm::Confirmation;
+use crate::heartbeat::LastBeat;
+use crate::ip_list::list_key;
+use crate::missing::Fallback;
+use crate::rate_limit::TokenBucket;
+use crate::retrying_client::RetryingClient;
+use crate::state::{Published, StateMap};
+use crate::statics::{CreatedRecord, StaticPlan};
+use ahash::{HashMap, HashMapExt};
+use anyhow::{anyhow, Context, Result};
+use futures::{future, FutureExt, StreamExt};
+use serde::de::IgnoredAny;
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::{IpAddr, SocketAddr};
+use std::num::NonZeroU8;
+use std::path::PathBuf;
+use std::pin::pin;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::join;
+use tokio::sync::Semaphore;
+use url::Url;
+
+mod authoritative;
+mod cloudflare;
+mod config;
+mod confirm;
+mod console_listener;
+#[doc(hidden)]
+pub mod daemon;
+mod detect;
+mod err;
+mod failover;
+mod ha;
+mod heartbeat;
+mod ip_list;
+mod lan;
+mod missing;
+mod network_listener;
+mod outside;
+mod pre;
+mod rate_limit;
+mod retrying_client;
+mod simulate;
+mod snapshot;
+mod state;
+mod statics;
+mod svcb;
+mod updaters;
+mod util;
+
+pub use crate::config::api_fields::{
+    Account, ApiFields, ApiFieldsBuilder, Candidate, DetectConfig, DetectMode, Failover, HaConfig,
+    Heartbeat, IpList, LanHost, OnMissing, OutsideChanges, PrefixSource, Probe, RoundRobin,
+    StaticRecord, VerifyConfig, Zone, ZoneBuilder,
+};
+pub use crate::config::family::{Families, IpFamily};
+pub use crate::config::ip_source::{IpSource, Sources};
+pub use crate::config::{
+    ClientConfig, Config, ConfigBuilder, GeneralConfig, HttpConfig, MiscConfig, RateLimitConfig,
+    RefreshConfig,
+};
+
+/// the comment on the records we create, when the config doesn't give them one
+const OWNER_MARKER: &str = "managed by cloudflare-ddns";
+
+type ZoneKey = (Option<Box<str>>, Box<str>);
+
+/// the dns permissions a zone's credentials lack, out of the ones cloudflare reported
+fn missing_permissions(granted: &[Box<str>]) -> Vec<&'static str> {
+    ["#dns_records:read", "#dns_records:edit"]
+        .into_iter()
+        .filter(|&permission| !granted.iter().any(|p| &**p == permission))
+        .collect()
+}
+type ListKey = (Box<str>, Box<str>);
+
+/// everything the daemon keeps between updates, like the zone ids it looked up and the http clients,
+/// build it once and reuse it
+pub struct DdnsContext {
+    client: RetryingClient,
+    cloudflare: cloudflare::Client,
+    user_messages: UserMessages,
+    zone_ids: Mutex<HashMap<ZoneKey, Arc<str>>>,
+    list_ids: Mutex<HashMap<ListKey, Arc<str>>>,
+    heartbeats: Mutex<HashMap<Box<str>, LastBeat>>,
+    nameservers: Mutex<HashMap<Arc<str>, Arc<[SocketAddr]>>>,
+    last_reconcile: Mutex<Option<Instant>>,
+    missing_since: Mutex<BTreeMap<IpFamily, Instant>>,
+    published: Published,
+    held: Published,
+    created: StateMap<CreatedRecord>,
+    snapshot_taken: tokio::sync::Mutex<bool>,
+    health: Arc<failover::Health>,
+    role: Mutex<Option<ha::Role>>,
+    state_dir: PathBuf,
+}
+
+/// sets up a [`DdnsContext`] beyond the defaults of [`DdnsContext::new`]
+pub struct DdnsContextBuilder {
+    cfg: Config,
+    state_dir: PathBuf,
+    notifier: Arc<dyn Notifier>,
+}
+
+impl DdnsContextBuilder {
+    /// where the published addresses, the created records and the snapshots are kept, `./state` by default
+    pub fn state_dir(mut self, state_dir: impl Into<PathBuf>) -> Self {
+        self.state_dir = state_dir.into();
+        self
+    }
+
+    /// where the warnings and errors go, [`StderrNotifier`] by default
+    pub fn notifier(mut self, notifier: impl Notifier) -> Self {
+        self.notifier = Arc::new(notifier);
+        self
+    }
+
+    pub fn build(self) -> DdnsContext {
+        let DdnsContextBuilder {
+            cfg,
+            state_dir,
+            notifier,
+        } = self;
+
+        let client = RetryingClient::new(&cfg);
+        let rate_limit = cfg.http().rate_limit();
+        let bucket = TokenBucket::new(rate_limit.requests(), rate_limit.period());
+        DdnsContext {
+            cloudflare: cloudflare::Client::new(
+                client.with_rate_limit(Arc::new(bucket)),
+                cfg.api_base().clone(),
+            ),
+            client,
+            user_messages: UserMessages::new(cfg.misc().general().max_errors(), notifier),
+            zone_ids: Mutex::new(HashMap::new()),
+            list_ids: Mutex::new(HashMap::new()),
+            heartbeats: Mutex::new(HashMap::new()),
+            nameservers: Mutex::new(HashMap::new()),
+            last_reconcile: Mutex::new(None),
+            missing_since: Mutex::new(BTreeMap::new()),
+            published: Published::load(&state_dir),
+            held: Published::load_file(state_dir.join(state::HELD_FILE)),
+            created: StateMap::load_file(state_dir.join(state::CREATED_FILE)),
+            snapshot_taken: tokio::sync::Mutex::new(false),
+            health: Arc::default(),
+            role: Mutex::new(None),
+            state_dir,
+        }
+    }
+}
+
+/// where the warnings and errors worth someone's attention go, like a record changed from outside
+///
+/// every call gets a thread of its own, so it's free to block until the message was seen
+pub trait Notifier: Send + Sync + 'static {
+    fn error(&self, msg: &str);
+
+    fn warning(&self, msg: &str);
+}
+
+/// writes every message to stderr
+pub struct StderrNotifier;
+
+impl Notifier for StderrNotifier {
+    fn error(&self, msg: &str) {
+        eprintln!("error: {msg}")
+    }
+
+    fn warning(&self, msg: &str) {
+        eprintln!("warning: {msg}")
+    }
+}
+
+/// an A or AAAA record, along with the address it holds
+#[derive(Debug)]
+pub struct Record {
+    ip: IpAddr,
+    dns: DnsRecord,
+}
+
+impl Record {
+    pub fn ip(&self) -> IpAddr {
+        self.ip
+    }
+
+    /// the id cloudflare gave the record
+    pub fn id(&self) -> &str {
+        &self.dns.id
+    }
+
+    /// the address as cloudflare holds it, [`Self::ip`] parsed
+    pub fn content(&self) -> &str {
+        &self.dns.content
+    }
+
+    pub fn proxied(&self) -> bool {
+        self.dns.proxied
+    }
+
+    /// in seconds, 1 is automatic
+    pub fn ttl(&self) -> u32 {
+        self.dns.ttl
+    }
+
+    pub fn comment(&self) -> Option<&str> {
+        self.dns.comment.as_deref()
+    }
+
+    fn body(zone: &Zone, ip: IpAddr) -> RecordBody<'_> {
+        RecordBody {
+            record_type: IpFamily::of(&ip).record_type(),
+            name: zone.record(),
+            content: Some(Cow::Owned(ip.to_string())),
+            proxied: Some(zone.proxied()),
+            ttl: zone.ttl(),
+            comment: zone.comment(),
+            tags: zone.tags(),
+            priority: None,
+            data: None,
+        }
+    }
+}
+
+/// what has to be sent to cloudflare for a record to match the config
+#[derive(Debug)]
+enum Pending {
+    Update {
+        id: Box<str>,
+        drift: String,
+        /// the address the record holds right now
+        previous: IpAddr,
+        content_changed: bool,
+        /// the record has no comment, so the update marks it as managed
+        unmarked: bool,
+    },
+    Create,
+}
+
+#[derive(Debug)]
+enum ChangeKind {
+    /// the A or AAAA record holding the address itself
+    Address {
+        family: IpFamily,
+        ip: IpAddr,
+        pending: Pending,
+    },
+    /// new address hints for an HTTPS or SVCB record
+    Hints {
+        id: Box<str>,
+        record_type: Box<str>,
+        data: SvcbData,
+    },
+    /// deleting our record, its address family went away
+    Remove { family: IpFamily, id: Box<str> },
+    /// a record declared under `[[records]]`
+    Static {
+        record_type: Box<str>,
+        content: Box<str>,
+        priority: Option<u16>,
+        /// the record to patch along with what drifted on it, `None` creates it
+        update: Option<(Box<str>, String)>,
+        /// the record has no comment, so it gets marked as managed
+        unmarked: bool,
+        /// the id cloudflare gave the record, once it was created
+        created: Option<Box<str>>,
+    },
+    /// deleting a record we created for `[[records]]` that isn't declared anymore
+    Forget { record_type: Box<str>, id: Box<str> },
+}
+
+/// what a record came to once it was compared with the config
+enum Planned<'a> {
+    Change(Change<'a>),
+    UpToDate,
+    /// changed outside the daemon, and left alone until it's acknowledged
+    Held,
+}
+
+impl<'a> From<Option<Change<'a>>> for Planned<'a> {
+    fn from(change: Option<Change<'a>>) -> Self {
+        change.map_or(Planned::UpToDate, Planned::Change)
+    }
+}
+
+/// what [`DdnsContext::update_record`] did with the record
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum RecordUpdate {
+    Updated,
+    UpToDate,
+    /// the record was changed outside the daemon, and the zone refuses to overwrite it until it's acknowledged
+    Held,
+}
+
+#[derive(Debug)]
+struct Change<'a> {
+    zone: &'a Zone,
+    kind: ChangeKind,
+}
+
+impl Change<'_> {
+    fn record_type(&self) -> &str {
+        match &self.kind {
+            ChangeKind::Address { family, .. } | ChangeKind::Remove { family, .. } => {
+                family.record_type()
+            }
+            ChangeKind::Hints { record_type, .. }
+            | ChangeKind::Static { record_type, .. }
+            | ChangeKind::Forget { record_type, .. } => record_type,
+        }
+    }
+
+    /// the record to patch or delete, `None` if the record has to be created
+    fn record_id(&self) -> Option<&str> {
+        match &self.kind {
+            ChangeKind::Address {
+                pending: Pending::Update { id, .. },
+                ..
+            }
+            | ChangeKind::Hints { id, .. }
+            | ChangeKind::Remove { id, .. }
+            | ChangeKind::Static {
+                update: Some((id, _)),
+                ..
+            }
+            | ChangeKind::Forget { id, .. } => Some(id),
+            ChangeKind::Address {
+                pending: Pending::Create,
+                ..
+            }
+            | ChangeKind::Static { update: None, .. } => None,
+        }
+    }
+
+    /// remembers the id a created record was given, only kept for the records we may delete later
+    fn created(&mut self, id: Box<str>) {
+        if let ChangeKind::Static { created, .. } = &mut self.kind {
+            *created = Some(id)
+        }
+    }
+
+    fn body(&self) -> RecordBody<'_> {
+        match &self.kind {
+            ChangeKind::Address { ip, pending, .. } => {
+                let mut body = Record::body(self.zone, *ip);
+                // a created record has to carry the marker that tells us it's ours
+                if let (Pending::Create, None, Some(RoundRobin::Tag(tag))) =
+                    (pending, body.tags, self.zone.round_robin())
+                {
+                    body.tags = Some(std::slice::from_ref(tag));
+                }
+                // and tells everyone else it's managed, without taking over the comments people wrote
+                let unmarked = match pending {
+                    Pending::Update { unmarked, .. } => *unmarked,
+                    Pending::Create => true,
+                };
+                if unmarked && body.comment.is_none() {
+                    body.comment = Some(OWNER_MARKER);
+                }
+                body
+            }
+            ChangeKind::Hints {
+                record_type, data, ..
+            } => RecordBody {
+                record_type,
+                name: self.zone.record(),
+                data: Some(data),
+                ..RecordBody::default()
+            },
+            ChangeKind::Remove { family, .. } => RecordBody {
+                record_type: family.record_type(),
+                name: self.zone.record(),
+                ..RecordBody::default()
+            },
+            ChangeKind::Static {
+                record_type,
+                content,
+                priority,
+                unmarked,
+                ..
+            } => RecordBody {
+                record_type,
+                name: self.zone.record(),
+                content: Some(Cow::Borrowed(content)),
+                proxied: StaticRecord::proxiable(record_type).then_some(self.zone.proxied()),
+                ttl: self.zone.ttl(),
+                comment: self.zone.comment().or(unmarked.then_some(OWNER_MARKER)),
+                tags: self.zone.tags(),
+                priority: *priority,
+                data: None,
+            },
+            ChangeKind::Forget { record_type, .. } => RecordBody {
+                record_type,
+                name: self.zone.record(),
+                ..RecordBody::default()
+            },
+        }
+    }
+}
+
+fn record_context(err: anyhow::Error, record_type: &str, record: &str) -> anyhow::Error {
+    err.context(format!(
+        "failed to update the {record_type} record {record}"
+    ))
+}
+
+impl DdnsContext {
+    /// a context keeping its state in `./state`, and writing warnings and errors to stderr
+    pub fn new(cfg: Config) -> Self {
+        Self::builder(cfg).build()
+    }
+
+    pub fn builder(cfg: Config) -> DdnsContextBuilder {
+        DdnsContextBuilder {
+            cfg,
+            state_dir: PathBuf::from(state::DEFAULT_STATE_DIR),
+            notifier: Arc::new(StderrNotifier),
+        }
+    }
+
+    /// resolves the current address, along with the source that answered first
+    pub async fn get_ip(&self, family: IpFamily, cfg: &Config) -> Result<(IpAddr, Url)> {
+        let last_err = Cell::new(None);
+
+        let iter = cfg
+            .ip_sources()
+            .filter(|source| source.family() == family)
+            .map(|source| {
+                let url = source.url().clone();
+                source
+                    .resolve_ip(&self.client, cfg)
+                    .map(move |res| res.map(|ip| (ip, url)))
+            });
+        let stream = futures::stream::iter(iter)
+            .buffer_unordered(cfg.concurrent_resolve().get() as usize)
+            .filter_map(|x| {
+                std::future::ready({
+                    match x {
+                        Ok(x) => Some(x),
+                        Err(err) => {
+                            last_err.set(Some(err));
+                            None
+                        }
+                    }
+                })
+            });
+
+        pin!(stream).next().await.ok_or_else(|| {
+            last_err
+                .take()
+                .unwrap_or(GetIpError::NoIpSources(family))
+                .into()
+        })
+    }
+
+    /// finds the zone with the longest name that contains the record,
+    /// only looking at the zones that match the configured zone name if there is one
+    async fn lookup_zone_id(&self, zone: &Zone) -> Result<Box<str>> {
+        let zones = self
+            .cloudflare
+            .list_zones(zone.account(), zone.name())
+            .await?;
+
+        let ZoneInfo { id, name, .. } = zones
+            .into_iter()
+            .filter(|info| util::is_subdomain(zone.record(), &info.name))
+            .max_by_key(|info| info.name.len())
+            .ok_or_else(|| match zone.name() {
+                Some(name) => anyhow!("the zone {name} isn't visible to this account"),
+                None => anyhow!(
+                    "none of the zones visible to this account contain {}",
+                    zone.record()
+                ),
+            })?;
+
+        dbg_println!("found the zone {name} ({id}) for {}", zone.record());
+        Ok(id)
+    }
+
+    async fn zone_id(&self, zone: &Zone) -> Result<Arc<str>> {
+        if let Some(id) = zone.id() {
+            return Ok(Arc::from(id));
+        }
+
+        let key = (zone.name().map(Box::from), Box::from(zone.record()));
+        if let Some(id) = self.zone_ids.lock().unwrap().get(&key) {
+            return Ok(Arc::clone(id));
+        }
+
+        let id = Arc::<str>::from(self.lookup_zone_id(zone).await?);
+        self.zone_ids.lock().unwrap().insert(key, Arc::clone(&id));
+        Ok(id)
+    }
+
+    async fn verify_account(&self, account: &Account) -> Result<()> {
+        // global api keys have no verify endpoint, the zone checks below still exercise them
+        if !account.uses_token() {
+            return Ok(());
+        }
+
+        let token = self.cloudflare.verify_token(account).await?;
+        anyhow::ensure!(
+            &*token.status == "active",
+            "the api token is {}, it has to be active to update records",
+            token.status
+        );
+        if let Some(expires_on) = token.expires_on {
+            dbg_println!("the api token expires on {expires_on}");
+        }
+        Ok(())
+    }
+
+    async fn verify_zone(&self, zone: &Zone) -> Result<()> {
+        let zone_id = self.zone_id(zone).await?;
+        let info = self.cloudflare.get_zone(zone.account(), &zone_id).await?;
+
+        if !info.permissions.is_empty() {
+            let missing = missing_permissions(&info.permissions);
+            anyhow::ensure!(
+                missing.is_empty(),
+                "the credentials used for {} are missing {} in the zone {}, \
+                 give the token the \"Zone / DNS / Edit\" permission",
+                zone.record(),
+                missing.join(" and "),
+                info.name
+            );
+        }
+
+        // listing the records proves read access even when cloudflare doesn't report permissions
+        let filter = RecordFilter {
+            record_type: None,
+            name: Some(zone.record()),
+        };
+        self.cloudflare
+            .list_dns_records::<IgnoredAny>(zone.account(), &zone_id, filter)
+            .await?;
+        Ok(())
+    }
+
+    /// checks every set of credentials, and that they can read and edit the dns of every zone,
+    /// so bad credentials fail at startup with an explanation instead of inside the update loop
+    ///
+    /// errors that might go away on their own, like having no internet yet, don't fail the check
+    async fn verify_access(&self, cfg: &Config) -> Result<()> {
+        let zones = cfg
+            .zones()
+            .iter()
+            .chain(cfg.records().iter().map(StaticRecord::zone))
+            .chain(cfg.ha().map(HaConfig::zone))
+            .collect::<Vec<_>>();
+
+        let mut accounts = Vec::<&Account>::new();
+        let used = (zones.iter().map(|zone| zone.account()))
+            .chain(cfg.ip_lists().iter().map(IpList::account));
+        for account in used {
+            if !accounts.iter().any(|&acc| std::ptr::eq(acc, account)) {
+                accounts.push(account)
+            }
+        }
+
+        let (accounts, zones, lists) = join!(
+            future::join_all(accounts.into_iter().map(|acc| self.verify_account(acc))),
+            future::join_all(zones.iter().map(|zone| {
+                self.verify_zone(zone)
+                    .map(|res| res.with_context(|| format!("unable to access {}", zone.record())))
+            })),
+            future::join_all(cfg.ip_lists().iter().map(|list| {
+                self.list_id(list).map(|res| {
+                    res.map(drop)
+                        .with_context(|| format!("unable to access the ip list {}", list.name()))
+                })
+            }))
+        );
+
+        let errors = accounts
+            .into_iter()
+            .chain(zones)
+            .chain(lists)
+            .filter_map(Result::err)
+            .filter(|err| {
+                let transient = err
+                    .downcast_ref::<ApiError>()
+                    .is_some_and(ApiError::is_transient);
+                if transient {
+                    dbg_println!("skipping the credential check: {err:#}");
+                }
+                !transient
+            })
+            .map(|err| format!("{err:#}"))
+            .collect::<Vec<_>>();
+
+        match &*errors {
+            [] => Ok(()),
+            [err] => Err(anyhow!("cloudflare credentials check failed: {err}")),
+            errors => Err(anyhow!(
+                "cloudflare credentials check failed:\n{}",
+                errors.join("\n")
+            )),
+        }
+    }
+
+    /// every A or AAAA record with the name of the zone, there's more than one for round-robin records
+    pub async fn records(&self, zone: &Zone, family: IpFamily) -> Result<Vec<Record>> {
+        let filter = RecordFilter {
+            record_type: Some(family.record_type()),
+            name: Some(zone.record()),
+        };
+
+        let records = self
+            .cloudflare
+            .list_dns_records::<DnsRecord>(zone.account(), &self.zone_id(zone).await?, filter)
+            .await?;
+
+        records
+            .into_iter()
+            .map(|dns| {
+                anyhow::ensure!(
+                    &*dns.name == zone.record() && &*dns.record_type == family.record_type(),
+                    "Expected {} {} found {} {}",
+                    family.record_type(),
+                    zone.record(),
+                    dns.record_type,
+                    dns.name
+                );
+
+                let ip = dns
+                    .content
+                    .parse()
+                    .with_context(|| format!("the record {} holds an invalid address", dns.name))?;
+                Ok(Record { ip, dns })
+            })
+            .collect()
+    }
+
+    /// picks the entry this daemon manages out of all the records with the configured name
+    fn own_record(
+        &self,
+        zone: &Zone,
+        family: IpFamily,
+        mut records: Vec<Record>,
+        current_ip: IpAddr,
+    ) -> Result<Option<Record>> {
+        let mut owned: Vec<Record> = match zone.round_robin() {
+            None => {
+                anyhow::ensure!(
+                    records.len() <= 1,
+                    "expected 1 {} record got {} records, \
+                     set round-robin to only manage our own entry: {records:?}",
+                    family.record_type(),
+                    records.len()
+                );
+                return Ok(records.pop());
+            }
+            Some(RoundRobin::Comment(comment)) => records
+                .into_iter()
+                .filter(|record| record.dns.comment.as_ref() == Some(comment))
+                .collect(),
+            Some(RoundRobin::Tag(tag)) => records
+                .into_iter()
+                .filter(|record| record.dns.tags.contains(tag))
+                .collect(),
+            Some(RoundRobin::LastIp) => {
+                let key = Published::key(zone.record(), family.record_type());
+                let published = self.published.get(&key);
+
+                // if our address is already in the set there is nothing to do
+                match records.iter().position(|record| record.ip == current_ip) {
+                    Some(pos) => vec![records.swap_remove(pos)],
+                    None => records
+                        .into_iter()
+                        .filter(|record| Some(record.ip) == published)
+                        .collect(),
+                }
+            }
+        };
+
+        anyhow::ensure!(
+            owned.len() <= 1,
+            "can't tell which {} record is ours, {} of them match: {owned:?}",
+            family.record_type(),
+            owned.len()
+        );
+
+        Ok(owned.pop())
+    }
+
+    /// lists every managed field of the record that doesn't match the config
+    fn drift(zone: &Zone, record: &Record, current_ip: IpAddr) -> Vec<String> {
+        let mut changes = vec![];
+
+        if record.ip != current_ip {
+            changes.push(format!("content {} -> {current_ip}", record.ip))
+        }
+
+        let dns = &record.dns;
+        if dns.proxied != zone.proxied() {
+            changes.push(format!("proxied {} -> {}", dns.proxied, zone.proxied()))
+        }
+
+        if let Some(ttl) = zone.ttl() {
+            if dns.ttl != ttl {
+                changes.push(format!("ttl {} -> {ttl}", dns.ttl))
+            }
+        }
+
+        if let Some(comment) = zone.comment() {
+            if dns.comment.as_deref() != Some(comment) {
+                changes.push(format!("comment {:?} -> {comment:?}", dns.comment))
+            }
+        }
+
+        if let Some(tags) = zone.tags() {
+            let mut current = dns.tags.clone();
+            current.sort_unstable();
+            current.dedup();
+            if *current != *tags {
+                changes.push(format!("tags {current:?} -> {tags:?}"))
+            }
+        }
+
+        changes
+    }
+
+    /// works out what has to change for our record to match the config, `None` if nothing does
+    fn plan_record(
+        &self,
+        zone: &Zone,
+        family: IpFamily,
+        records: Vec<Record>,
+        current_ip: IpAddr,
+    ) -> Result<Option<Pending>> {
+        match self.own_record(zone, family, records, current_ip)? {
+            Some(record) => {
+                let changes = Self::drift(zone, &record, current_ip);
+                if changes.is_empty() {
+                    return Ok(None);
+                }
+
+                let drift = changes.join(", ");
+                dbg_println!("updating {}: {drift}", zone.record());
+                Ok(Some(Pending::Update {
+                    id: record.dns.id,
+                    drift,
+                    previous: record.ip,
+                    content_changed: record.ip != current_ip,
+                    unmarked: record.dns.comment.is_none(),
+                }))
+            }
+            None if zone.create_if_missing() => Ok(Some(Pending::Create)),
+            None => anyhow::bail!(
+                "the record doesn't exist, create it from the dashboard or set create-if-missing"
+            ),
+        }
+    }
+
+    /// works out the change our record needs
+    async fn plan_address<'a>(
+        &self,
+        zone: &'a Zone,
+        family: IpFamily,
+        records: Vec<Record>,
+        ip: IpAddr,
+    ) -> Result<Planned<'a>> {
+        match self.plan_record(zone, family, records, ip)? {
+            Some(pending) => match self.allow_overwrite(zone, family, &pending).await? {
+                true => Ok(Planned::Change(Change {
+                    zone,
+                    kind: ChangeKind::Address {
+                        family,
+                        ip,
+                        pending,
+                    },
+                })),
+                false => Ok(Planned::Held),
+            },
+            None => self
+                .save_published(zone, family, ip)
+                .await
+                .map(|()| Planned::UpToDate),
+        }
+    }
+
+    async fn save_published(&self, zone: &Zone, family: IpFamily, ip: IpAddr) -> Result<()> {
+        let key = Published::key(zone.record(), family.record_type());
+        self.published
+            .set(key, ip)
+            .await
+            .context("unable to save the published address")
+    }
+
+    async fn apply_change(&self, zone_id: &str, change: &mut Change<'_>) -> Result<()> {
+        let account = change.zone.account();
+        match &change.kind {
+            ChangeKind::Remove { id, .. } => {
+                return self
+                    .cloudflare
+                    .delete_dns_record(account, zone_id, id)
+                    .await
+                    .context("unable to delete the record");
+            }
+            // a leftover someone else already deleted is just as good
+            ChangeKind::Forget { id, .. } => {
+                return match self
+                    .cloudflare
+                    .delete_dns_record(account, zone_id, id)
+                    .await
+                {
+                    Ok(()) | Err(ApiError::NotFound(_)) => Ok(()),
+                    Err(err) => {
+                        Err(anyhow::Error::from(err).context("unable to delete the record"))
+                    }
+                };
+            }
+            _ => {}
+        }
+
+        match change.record_id() {
+            Some(id) => {
+                self.cloudflare
+                    .patch_dns_record(account, zone_id, id, &change.body())
+                    .await?;
+            }
+            None => {
+                let record = self
+                    .cloudflare
+                    .create_dns_record(account, zone_id, &change.body())
+                    .await
+                    .context("unable to create the record")?;
+                change.created(record.id);
+            }
+        }
+        Ok(())
+    }
+
+    async fn apply_batch(
+        &self,
+        zone_id: &str,
+        account: &Account,
+        changes: &mut [Change<'_>],
+    ) -> Result<()> {
+        let mut batch = BatchBody::default();
+        for change in changes.iter() {
+            if let ChangeKind::Remove { id, .. } | ChangeKind::Forget { id, .. } = &change.kind {
+                batch.deletes.push(BatchDelete { id });
+                continue;
+            }
+
+            match change.record_id() {
+                Some(id) => batch.patches.push(BatchPatch {
+                    id,
+                    body: change.body(),
+                }),
+                None => batch.posts.push(change.body()),
+            }
+        }
+
+        let result = self
+            .cloudflare
+            .batch_dns_records(account, zone_id, &batch)
+            .await?;
+
+        anyhow::ensure!(
+            result.deletes.len() == batch.deletes.len()
+                && result.patches.len() == batch.patches.len()
+                && result.posts.len() == batch.posts.len(),
+            "cloudflare only applied {} of the {} changes in the batch",
+            result.deletes.len() + result.patches.len() + result.posts.len(),
+            changes.len()
+        );
+
+        // posts come back in the order they were sent
+        let created = changes
+            .iter_mut()
+            .filter(|change| change.record_id().is_none());
+        for (change, record) in created.zip(result.posts) {
+            change.created(record.id)
+        }
+        Ok(())
+    }
+
+    /// applies all the changes to one zone in a single batch, so the zone is never left half updated,
+    /// falling back to one call per record if the batch is rejected
+    async fn apply_zone_changes<'a>(
+        &self,
+        zone_id: &str,
+        account: &Account,
+        mut changes: Vec<Change<'a>>,
+    ) -> Vec<(Change<'a>, Result<()>)> {
+        if changes.len() > 1 {
+            match self.apply_batch(zone_id, account, &mut changes).await {
+                Ok(()) => {
+                    dbg_println!("updated {} records in zone {zone_id} in one batch", changes.len());
+                    return changes.into_iter().map(|change| (change, Ok(()))).collect();
+                }
+                Err(err) => dbg_println!(
+                    "the batch for zone {zone_id} was rejected, updating records one by one: {err:#}"
+                ),
+            }
+        }
+
+        future::join_all(changes.into_iter().map(|mut change| async move {
+            let res = self.apply_change(zone_id, &mut change).await;
+            (change, res)
+        }))
+        .await
+    }
+
+    /// groups the changes by zone and credentials, and applies every group
+    async fn apply_changes<'a>(&self, changes: Vec<Change<'a>>) -> Vec<(Change<'a>, Result<()>)> {
+        let zone_ids =
+            future::join_all(changes.iter().map(|change| self.zone_id(change.zone))).await;
+
+        let existing = changes
+            .iter()
+            .zip(&zone_ids)
+            .filter_map(|(change, zone_id)| {
+                let zone_id = zone_id.as_deref().ok()?;
+                Some((change.zone, zone_id, change.record_id()?))
+            })
+            .collect::<Vec<_>>();
+        if let Err(err) = self.snapshot_once(&existing).await {
+            // writing without a way back is worse than waiting for the next update
+            let err = format!("{err:#}");
+            return changes
+                .into_iter()
+                .map(|change| {
+                    let err = anyhow!("unable to snapshot the records before changing them: {err}");
+                    (change, Err(err))
+                })
+                .collect();
+        }
+
+        let mut results = vec![];
+        let mut groups = Vec::<(Arc<str>, &Account, Vec<Change<'a>>)>::new();
+        for (change, zone_id) in changes.into_iter().zip(zone_ids) {
+            let zone_id = match zone_id {
+                Ok(zone_id) => zone_id,
+                Err(err) => {
+                    results.push((change, Err(err)));
+                    continue;
+                }
+            };
+
+            let account = change.zone.account();
+            match groups
+                .iter_mut()
+                .find(|(id, acc, _)| *id == zone_id && std::ptr::eq(*acc, account))
+            {
+                Some((_, _, group)) => group.push(change),
+                None => groups.push((zone_id, account, vec![change])),
+            }
+        }
+
+        let applied = future::join_all(groups.iter_mut().map(|(zone_id, account, group)| {
+            self.apply_zone_changes(zone_id, account, std::mem::take(group))
+        }))
+        .await;
+
+        results.extend(applied.into_iter().flatten());
+        results
+    }
+
+    /// reports a change that went through, and remembers the address we published
+    async fn finish_change(&self, change: &Change<'_>) -> Result<()> {
+        let zone = change.zone;
+        let (family, ip, pending) = match &change.kind {
+            ChangeKind::Address {
+                family,
+                ip,
+                pending,
+            } => (*family, *ip, pending),
+            ChangeKind::Hints { record_type, .. } => {
+                dbg_println!(
+                    "updated the address hints of the {record_type} record {}",
+                    zone.record()
+                );
+                return Ok(());
+            }
+            ChangeKind::Remove { family, .. } => {
+                return self.finish_removal(zone, *family).await;
+            }
+            ChangeKind::Static { .. } | ChangeKind::Forget { .. } => {
+                return self.finish_static(change).await;
+            }
+        };
+
+        match pending {
+            Pending::Update {
+                drift,
+                content_changed: false,
+                ..
+            } => dbg_println!(
+                "the {} record {} drifted from the config, corrected {drift}",
+                family.record_type(),
+                zone.record()
+            ),
+            Pending::Update { .. } => {}
+            Pending::Create => dbg_println!("created the record {}", zone.record()),
+        }
+
+        self.save_published(zone, family, ip).await
+    }
+
+    /// points our record of the zone at `ip`, and corrects the settings that drifted from the config
+    ///
+    /// only the record itself is updated, heartbeats, address hints and the verification are left to [`Self::run_ddns`]
+    pub async fn update_record(&self, zone: &Zone, ip: IpAddr) -> Result<RecordUpdate> {
+        let family = IpFamily::of(&ip);
+        let update = async {
+            let records = self.records(zone, family).await?;
+            let change = match self.plan_address(zone, family, records, ip).await? {
+                Planned::Change(change) => change,
+                Planned::UpToDate => return Ok(RecordUpdate::UpToDate),
+                Planned::Held => return Ok(RecordUpdate::Held),
+            };
+
+            for (change, res) in self.apply_changes(vec![change]).await {
+                res?;
+                self.finish_change(&change).await?;
+            }
+            Ok(RecordUpdate::Updated)
+        };
+
+        update
+            .await
+            .map_err(|err| record_context(err, family.record_type(), zone.record()))
+    }
+
+    /// resolves every address family in use once, and shares it between all the records,
+    /// a record failing does not stop the other ones from being updated
+    ///
+    /// with change detection on, only the records that look out of date are read from the api,
+    /// except during the periodic reconciliation
+    pub async fn run_ddns(&self, cfg: Config) -> DdnsReport {
+        let detect = cfg.detect();
+        let full = self.reconcile_due(detect);
+        if !full {
+            dbg_println!("looking for changes without the api");
+        }
+
+        let targets = cfg
+            .zones()
+            .iter()
+            .flat_map(|zone| zone.family().iter().map(move |family| (zone, family)))
+            .collect::<Vec<_>>();
+
+        // lan hosts that take their prefix from an interface don't need the ip sources
+        let families = targets
+            .iter()
+            .filter(|&&(zone, family)| {
+                let prefix_from = zone.lan_host().map(LanHost::prefix_from);
+                !matches!(
+                    (family, prefix_from),
+                    (IpFamily::V6, Some(PrefixSource::Iface(_)))
+                )
+            })
+            .map(|&(_, family)| family)
+            .chain(cfg.ip_lists().iter().flat_map(|list| list.family().iter()))
+            .collect::<BTreeSet<_>>();
+
+        let current_ips = future::join_all(
+            families
+                .iter()
+                .copied()
+                .map(|family| self.get_ip(family, &cfg).map(move |ip| (family, ip))),
+        )
+        .await;
+
+        let mut report = DdnsReport::default();
+
+        let resolved = current_ips
+            .into_iter()
+            .filter_map(|(family, ip)| match ip {
+                Ok(ip) => Some((family, ip)),
+                Err(err) => {
+                    let err =
+                        err.context(format!("unable to resolve the current {family} address"));
+                    report.errors.push(err);
+                    None
+                }
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        let current_ips = resolved
+            .iter()
+            .map(|(&family, &(ip, _))| (family, ip))
+            .collect::<BTreeMap<_, _>>();
+
+        // a family that stays unresolvable past the grace period gets the zone's on-missing policy
+        let missing = self.track_missing(&families, &current_ips);
+        let mut removals = vec![];
+        let targets = targets
+            .into_iter()
+            .filter_map(|(zone, family)| {
+                let resolved = current_ips.get(&family).copied();
+                let ip = match zone.lan_host().filter(|_| family == IpFamily::V6) {
+                    Some(host) => match lan::host_address(host, resolved) {
+                        Ok(ip) => ip,
+                        Err(err) => {
+                            let err = anyhow::Error::from(err).context("unable to find the prefix");
+                            report.errors.push(record_context(
+                                err,
+                                family.record_type(),
+                                zone.record(),
+                            ));
+                            return None;
+                        }
+                    },
+                    None => resolved,
+                };
+                let ip = self.health.target(zone, family, ip);
+                if let Some(ip) = ip {
+                    return Some((zone, family, ip));
+                }
+
+                match missing::fallback(zone, family, missing.get(&family).copied()) {
+                    Fallback::Placeholder(ip) => Some((zone, family, ip)),
+                    Fallback::Delete => {
+                        removals.push((zone, family));
+                        None
+                    }
+                    Fallback::Leave => None,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        // the address hints of a zone follow its records, so they're worked out from every target, not just the stale ones
+        let hint_targets = targets.clone();
+
+        let checks = future::join_all(targets.iter().map(|&(zone, family, ip)| async move {
+            match detect {
+                Some(detect) if !full => self.up_to_date(zone, family, ip, detect).await,
+                _ => false,
+            }
+        }))
+        .await;
+
+        let mut stale = vec![];
+        for (target, up_to_date) in targets.into_iter().zip(checks) {
+            match up_to_date {
+                true => report.unchanged += 1,
+                false => stale.push(target),
+            }
+        }
+
+        // hints only go out of date along with the address records of their zone
+        let svcb_zones = cfg
+            .zones()
+            .iter()
+            .filter(|&zone| {
+                zone.svcb_hints()
+                    && (full || stale.iter().any(|&(other, ..)| std::ptr::eq(other, zone)))
+            })
+            .collect::<Vec<_>>();
+
+        let (records, svcb_records) = join!(
+            future::join_all(
+                stale
+                    .iter()
+                    .map(|&(zone, family, _)| self.records(zone, family))
+            ),
+            future::join_all(svcb_zones.iter().map(|&zone| self.get_svcb_records(zone)))
+        );
+
+        let plans =
+            stale
+                .into_iter()
+                .zip(records)
+                .map(|((zone, family, ip), records)| async move {
+                    match records {
+                        Ok(records) => self.plan_address(zone, family, records, ip).await,
+                        Err(err) => Err(err),
+                    }
+                    .map_err(|err| record_context(err, family.record_type(), zone.record()))
+                });
+
+        let removals = removals.into_iter().map(|(zone, family)| {
+            self.plan_removal(zone, family, full).map(move |res| {
+                res.map(Planned::from)
+                    .map_err(|err| record_context(err, family.record_type(), zone.record()))
+            })
+        });
+
+        // declared records only drift through the dashboard, like the settings of our own,
+        // so they're checked along with every full read of the api
+        let leftovers = match full {
+            true => self.leftover_records(&cfg),
+            false => vec![],
+        };
+        let statics = async {
+            match full {
+                true => self.plan_static_records(cfg.records(), &leftovers).await,
+                false => StaticPlan::default(),
+            }
+        };
+
+        let (plans, removals, statics) =
+            join!(future::join_all(plans), future::join_all(removals), statics);
+        let mut changes = statics.changes;
+        report.unchanged += statics.unchanged;
+        report.errors.extend(statics.errors);
+        for plan in plans.into_iter().chain(removals) {
+            match plan {
+                Ok(Planned::Change(change)) => changes.push(change),
+                Ok(Planned::UpToDate) => report.unchanged += 1,
+                Ok(Planned::Held) => report.held += 1,
+                Err(err) => report.errors.push(err),
+            }
+        }
+
+        for (zone, records) in svcb_zones.into_iter().zip(svcb_records) {
+            match records {
+                Ok(records) => {
+                    let total = records.len();
+                    let hints = Self::plan_hints(zone, records, &hint_targets);
+                    report.unchanged += total - hints.len();
+                    changes.extend(hints);
+                }
+                Err(err) => report.errors.push(err.context(format!(
+                    "failed to update the address hints of {}",
+                    zone.record()
+                ))),
+            }
+        }
+
+        // a list only takes one bulk operation at a time, so its families are synced one after another
+        let list_syncs = cfg.ip_lists().iter().map(|list| {
+            let current_ips = &current_ips;
+            async move {
+                let mut results = vec![];
+                for family in list.family().iter() {
+                    let Some(&ip) = current_ips.get(&family) else {
+                        continue;
+                    };
+                    // lists can only be checked through the api, so outside a reconciliation
+                    // they're compared against the address we last put in them
+                    if !full && self.published.get(&list_key(list, family)) == Some(ip) {
+                        results.push(Ok(false));
+                        continue;
+                    }
+                    let res = self.sync_ip_list(list, family, ip).await.with_context(|| {
+                        format!(
+                            "failed to update the {family} entry of the ip list {}",
+                            list.name()
+                        )
+                    });
+                    results.push(res);
+                }
+                results
+            }
+        });
+
+        let (applied, lists) = join!(self.apply_changes(changes), future::join_all(list_syncs));
+
+        for res in lists.into_iter().flatten() {
+            match res {
+                Ok(true) => report.updated += 1,
+                Ok(false) => report.unchanged += 1,
+                Err(err) => report.errors.push(err),
+            }
+        }
+
+        let mut done = vec![];
+        for (change, res) in applied {
+            let res = match res {
+                Ok(()) => self.finish_change(&change).await,
+                Err(err) => Err(err),
+            };
+
+            match res {
+                Ok(()) => {
+                    report.updated += 1;
+                    done.push(change)
+                }
+                Err(err) => report.errors.push(record_context(
+                    err,
+                    change.record_type(),
+                    change.zone.record(),
+                )),
+            }
+        }
+
+        if let Some(verify) = cfg.verify() {
+            let confirmations = done.iter().filter_map(|change| match change.kind {
+                ChangeKind::Address { family, ip, .. } => Some(
+                    self.confirm(change.zone, family, ip, verify)
+                        .map(move |confirmation| (change, confirmation)),
+                ),
+                ChangeKind::Hints { .. }
+                | ChangeKind::Remove { .. }
+                | ChangeKind::Static { .. }
+                | ChangeKind::Forget { .. } => None,
+            });
+
+            for (change, confirmation) in future::join_all(confirmations).await {
+                dbg_println!(
+                    "the {} record {} is {confirmation:?}",
+                    change.record_type(),
+                    change.zone.record()
+                );
+                match confirmation {
+                    Confirmation::Verified => report.verified += 1,
+                    Confirmation::Pending => report.pending += 1,
+                    Confirmation::Failed(err) => report.errors.push(err.context(format!(
+                        "the update of the {} record {} could not be confirmed",
+                        change.record_type(),
+                        change.zone.record()
+                    ))),
+                }
+            }
+        }
+
+        // written after the updates, so the heartbeat reflects what was just published
+        let heartbeats = cfg.zones().iter().filter_map(|zone| {
+            let heartbeat = zone.heartbeat()?;
+            Some(self.heartbeat(zone, heartbeat, &resolved).map(move |res| {
+                res.with_context(|| format!("failed to write the heartbeat {}", heartbeat.name()))
+            }))
+        });
+
+        for res in future::join_all(heartbeats).await {
+            if let Err(err) = res {
+                report.errors.push(err)
+            }
+        }
+
+        report
+    }
+}
+
+/// how an update went, a record failing doesn't stop the others so errors don't fail the whole update
+#[derive(Debug, Default)]
+pub struct DdnsReport {
+    updated: usize,
+    unchanged: usize,
+    /// updates that were confirmed to be served, only counted when verification is on
+    verified: usize,
+    /// updates the api holds but the nameservers didn't serve in time
+    pending: usize,
+    /// records changed outside the daemon that are left alone until they're acknowledged
+    held: usize,
+    errors: Vec<anyhow::Error>,
+}
+
+impl DdnsReport {
+    pub fn updated(&self) -> usize {
+        self.updated
+    }
+
+    pub fn unchanged(&self) -> usize {
+        self.unchanged
+    }
+
+    /// updates that were confirmed to be served, only counted when verification is on
+    pub fn verified(&self) -> usize {
+        self.verified
+    }
+
+    /// updates the api holds but the nameservers didn't serve in time
+    pub fn pending(&self) -> usize {
+        self.pending
+    }
+
+    /// records changed outside the daemon that are left alone until they're acknowledged
+    pub fn held(&self) -> usize {
+        self.held
+    }
+
+    pub fn errors(&self) -> &[anyhow::Error] {
+        &self.errors
+    }
+}
+
+#[derive(Clone)]
+struct UserMessages {
+    errors: Arc<Semaphore>,
+    warning: Arc<Semaphore>,
+    notifier: Arc<dyn Notifier>,
+}
+
+impl UserMessages {
+    fn new(max_errors: NonZeroU8, notifier: Arc<dyn Notifier>) -> Self {
+        let permits = max_errors.get() as usize;
+        UserMessages {
+            errors: Arc::new(Semaphore::new(permits)),
+            warning: Arc::new(Semaphore::new(permits)),
+            notifier,
+        }
+    }
+
+    async fn custom_error(&self, fun: impl FnOnce() + Send + 'static) {
+        err::spawn_message_box(Arc::clone(&self.errors), fun).await
+    }
+
+    async fn custom_warning(&self, fun: impl FnOnce() + Send + 'static) {
+        err::spawn_message_box(Arc::clone(&self.warning), fun).await
+    }
+
+    async fn error(&self, msg: impl Into<Cow<'static, str>>) {
+        let (msg, notifier) = (msg.into(), Arc::clone(&self.notifier));
+        self.custom_error(move || notifier.error(&msg)).await
+    }
+
+    async fn warning(&self, msg: impl Into<Cow<'static, str>>) {
+        let (msg, notifier) = (msg.into(), Arc::clone(&self.notifier));
+        self.custom_warning(move || notifier.warning(&msg)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a context keeping its state in a scratch directory of its own
+    pub(crate) fn context(cfg: &Config, test: &str) -> DdnsContext {
+        let state_dir =
+            std::env::temp_dir().join(format!("cloudflare-ddns-{test}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&state_dir);
+        DdnsContext::builder(cfg.clone())
+            .state_dir(state_dir)
+            .build()
+    }
+
+    fn granted(permissions: &[&str]) -> Vec<Box<str>> {
+        permissions.iter().map(|&p| Box::from(p)).collect()
+    }
+
+    #[test]
+    fn needs_to_read_and_edit_dns() {
+        let all = granted(&["#zone:read", "#dns_records:read", "#dns_records:edit"]);
+        assert!(missing_permissions(&all).is_empty());
+
+        let read_only = granted(&["#zone:read", "#dns_records:read"]);
+        assert_eq!(missing_permissions(&read_only), ["#dns_records:edit"]);
+
+        let none = granted(&["#zone:read"]);
+        assert_eq!(
+            missing_permissions(&none),
+            ["#dns_records:read", "#dns_records:edit"]
+        );
+    }
+
+    #[tokio::test]
+    async fn updates_records_one_by_one_when_the_batch_is_rejected() {
+        let batches = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let (api, fake) = simulate::fake_api(
+            r#"
+            [account]
+            api-token = "8dY3nH-As0krmv83n3pm1l"
+
+            [[zone]]
+            record = "home.example.com"
+            family = "v4"
+
+            [[zone]]
+            record = "office.example.com"
+            family = "v4"
+            "#,
+            {
+                let batches = Arc::clone(&batches);
+                move |path| {
+                    let batch = path.ends_with("/batch");
+                    if batch {
+                        batches.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                    }
+                    batch
+                }
+            },
+        )
+        .await
+        .unwrap();
+        let cfg = Config::builder(api).build();
+        let ctx = context(&cfg, "batch-fallback");
+
+        let ip = ip_macro::ip!("203.0.113.7");
+        let mut changes = vec![];
+        for zone in cfg.zones() {
+            let records = ctx.records(zone, IpFamily::V4).await.unwrap();
+            match ctx.plan_address(zone, IpFamily::V4, records, ip).await {
+                Ok(Planned::Change(change)) => changes.push(change),
+                _ => panic!("{} should need an update", zone.record()),
+            }
+        }
+
+        for (change, res) in ctx.apply_changes(changes).await {
+            res.unwrap_or_else(|err| panic!("{}: {err:#}", change.zone.record()));
+        }
+        assert_eq!(batches.load(std::sync::atomic::Ordering::Relaxed), 1);
+        for zone in cfg.zones() {
+            assert_eq!(fake.addresses(zone.record(), "A"), [ip]);
+        }
+    }
+}
@@ -0,0 +1,189 @@
+use crate::config::api_fields::{OnMissing, Zone};
+use crate::config::family::IpFamily;
+use crate::state::Published;
+use crate::{Change, ChangeKind, DdnsContext};
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::{Duration, Instant};
+
+/// what becomes of a record whose family can't be resolved
+#[derive(Debug, Eq, PartialEq)]
+pub(crate) enum Fallback {
+    Leave,
+    Placeholder(IpAddr),
+    Delete,
+}
+
+/// applies the zone's on-missing policy, once the family has been gone for the zone's missing-grace
+pub(crate) fn fallback(zone: &Zone, family: IpFamily, gone: Option<Duration>) -> Fallback {
+    if gone.is_none_or(|gone| gone < zone.missing_grace()) {
+        return Fallback::Leave;
+    }
+
+    match zone.on_missing() {
+        OnMissing::Keep => Fallback::Leave,
+        OnMissing::Delete => Fallback::Delete,
+        OnMissing::Placeholder(placeholders) => placeholders
+            .iter()
+            .find(|ip| IpFamily::of(ip) == family)
+            .map_or(Fallback::Leave, |&ip| Fallback::Placeholder(ip)),
+    }
+}
+
+impl DdnsContext {
+    /// remembers since when every family in use failed to resolve, forgetting the ones that are back,
+    /// and returns how long each missing family has been gone
+    pub(crate) fn track_missing(
+        &self,
+        families: &BTreeSet<IpFamily>,
+        current_ips: &BTreeMap<IpFamily, IpAddr>,
+    ) -> BTreeMap<IpFamily, Duration> {
+        let mut missing_since = self.missing_since.lock().unwrap();
+        missing_since.retain(|family, _| !current_ips.contains_key(family));
+        for &family in families {
+            if !current_ips.contains_key(&family) {
+                missing_since.entry(family).or_insert_with(Instant::now);
+            }
+        }
+
+        missing_since
+            .iter()
+            .map(|(&family, since)| (family, since.elapsed()))
+            .collect()
+    }
+
+    /// finds our record of a family that went away, `None` once it's already gone
+    ///
+    /// the published address is forgotten after a removal, so outside a reconciliation
+    /// there's nothing left to look for
+    pub(crate) async fn plan_removal<'a>(
+        &self,
+        zone: &'a Zone,
+        family: IpFamily,
+        full: bool,
+    ) -> Result<Option<Change<'a>>> {
+        let key = Published::key(zone.record(), family.record_type());
+        let published = self.published.get(&key);
+        if !full && published.is_none() {
+            return Ok(None);
+        }
+
+        let records = self.records(zone, family).await?;
+        // an address nothing can hold, so round-robin only finds our entry through the published one
+        let anchor = published.unwrap_or(match family {
+            IpFamily::V4 => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            IpFamily::V6 => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        });
+
+        Ok(self
+            .own_record(zone, family, records, anchor)?
+            .map(|record| Change {
+                zone,
+                kind: ChangeKind::Remove {
+                    family,
+                    id: record.dns.id,
+                },
+            }))
+    }
+
+    pub(crate) async fn finish_removal(&self, zone: &Zone, family: IpFamily) -> Result<()> {
+        self.user_messages
+            .warning(format!(
+                "no {family} address could be found for {:?}, deleted the {} record {}",
+                zone.missing_grace(),
+                family.record_type(),
+                zone.record()
+            ))
+            .await;
+
+        let key = Published::key(zone.record(), family.record_type());
+        self.published
+            .remove(&key)
+            .await
+            .context("unable to forget the published address")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::api_fields::ApiFields;
+    use crate::config::Config;
+
+    fn zone(on_missing: &str) -> Zone {
+        let api: ApiFields = format!(
+            r#"
+            [account]
+            api-token = "8dY3nH-As0krmv83n3pm1l"
+
+            [[zone]]
+            record = "home.example.com"
+            family = "v4"
+            create-if-missing = true
+            on-missing = {on_missing}
+            missing-grace = 00:30:00
+            "#
+        )
+        .parse()
+        .unwrap();
+        api.zones.into_vec().remove(0)
+    }
+
+    const MINUTE: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn waits_out_the_grace() {
+        let zone = zone(r#""delete""#);
+        assert_eq!(fallback(&zone, IpFamily::V4, None), Fallback::Leave);
+        assert_eq!(
+            fallback(&zone, IpFamily::V4, Some(29 * MINUTE)),
+            Fallback::Leave
+        );
+        assert_eq!(
+            fallback(&zone, IpFamily::V4, Some(30 * MINUTE)),
+            Fallback::Delete
+        );
+    }
+
+    #[test]
+    fn takes_the_placeholder_of_the_family() {
+        let zone = zone(r#"{ placeholder = ["2001:db8::1", "192.0.2.1"] }"#);
+        assert_eq!(
+            fallback(&zone, IpFamily::V4, Some(45 * MINUTE)),
+            Fallback::Placeholder(ip_macro::ip!("192.0.2.1"))
+        );
+    }
+
+    #[test]
+    fn keeps_the_record_forever() {
+        let zone = zone(r#""keep""#);
+        assert_eq!(
+            fallback(&zone, IpFamily::V4, Some(24 * 60 * MINUTE)),
+            Fallback::Leave
+        );
+    }
+
+    #[test]
+    fn forgets_families_once_they_are_back() {
+        let api: ApiFields = r#"
+            [account]
+            api-token = "8dY3nH-As0krmv83n3pm1l"
+
+            [[zone]]
+            record = "home.example.com"
+            "#
+        .parse()
+        .unwrap();
+        let ctx = crate::tests::context(&Config::builder(api).build(), "missing");
+        let families = BTreeSet::from([IpFamily::V4, IpFamily::V6]);
+
+        let v4 = BTreeMap::from([(IpFamily::V4, ip_macro::ip!("203.0.113.7"))]);
+        let missing = ctx.track_missing(&families, &v4);
+        assert_eq!(missing.keys().collect::<Vec<_>>(), [&IpFamily::V6]);
+
+        let v6 = BTreeMap::from([(IpFamily::V6, ip_macro::ip!("2001:db8::7"))]);
+        let missing = ctx.track_missing(&families, &v6);
+        assert_eq!(missing.keys().collect::<Vec<_>>(), [&IpFamily::V4]);
+    }
+}
@@ -0,0 +1,195 @@
+use crate::config::api_fields::{OutsideChanges, Zone};
+use crate::config::family::IpFamily;
+use crate::state::{Published, ACKNOWLEDGED_FILE};
+use crate::{dbg_println, DdnsContext, Pending};
+use anyhow::{Context, Result};
+
+impl DdnsContext {
+    /// checks an update against the address we last wrote, a record that holds something else
+    /// was changed by someone other than us, maybe on purpose during an incident
+    ///
+    /// returns whether the update may go ahead
+    pub(crate) async fn allow_overwrite(
+        &self,
+        zone: &Zone,
+        family: IpFamily,
+        pending: &Pending,
+    ) -> Result<bool> {
+        let &Pending::Update {
+            previous,
+            content_changed: true,
+            ..
+        } = pending
+        else {
+            return Ok(true);
+        };
+
+        let key = Published::key(zone.record(), family.record_type());
+        let written = match self.published.get(&key) {
+            Some(written) if written != previous => written,
+            // we never wrote it, or it still holds what we wrote
+            _ => return self.release(&key).await.map(|()| true),
+        };
+
+        let record_type = family.record_type();
+        let record = zone.record();
+        match zone.outside_changes() {
+            OutsideChanges::Overwrite => Ok(true),
+            OutsideChanges::Warn => {
+                self.user_messages
+                    .warning(format!(
+                        "the {record_type} record {record} was changed to {previous} outside of cloudflare-ddns, \
+                         overwriting what we last wrote ({written})"
+                    ))
+                    .await;
+                Ok(true)
+            }
+            OutsideChanges::Refuse => {
+                // read fresh every time, the acknowledge subcommand writes it from another process
+                let acknowledged = Published::load_file(self.state_dir.join(ACKNOWLEDGED_FILE));
+                if acknowledged.get(&key) == Some(previous) {
+                    dbg_println!("the change to {record} was acknowledged, overwriting {previous}");
+                    acknowledged
+                        .remove(&key)
+                        .await
+                        .context("unable to save the acknowledged records")?;
+                    self.release(&key).await?;
+                    return Ok(true);
+                }
+
+                // only warn once for every outside change
+                if self.held.get(&key) != Some(previous) {
+                    self.held
+                        .set(key, previous)
+                        .await
+                        .context("unable to save the held records")?;
+                    self.user_messages
+                        .warning(format!(
+                            "the {record_type} record {record} was changed to {previous} outside of cloudflare-ddns, \
+                             it's left alone until `cloudflare-ddns acknowledge` is run"
+                        ))
+                        .await;
+                }
+                Ok(false)
+            }
+        }
+    }
+
+    async fn release(&self, key: &str) -> Result<()> {
+        self.held
+            .remove(key)
+            .await
+            .context("unable to save the held records")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::api_fields::ApiFields;
+    use crate::config::Config;
+    use crate::state;
+    use std::net::IpAddr;
+
+    fn config(outside_changes: &str) -> Config {
+        let api: ApiFields = format!(
+            r#"
+            [account]
+            api-token = "8dY3nH-As0krmv83n3pm1l"
+
+            [[zone]]
+            record = "home.example.com"
+            family = "v4"
+            outside-changes = "{outside_changes}"
+            "#
+        )
+        .parse()
+        .unwrap();
+        Config::builder(api).build()
+    }
+
+    /// an update of a record that holds `previous`
+    fn update(previous: IpAddr) -> Pending {
+        Pending::Update {
+            id: "1".into(),
+            drift: format!("content {previous} -> 203.0.113.8"),
+            previous,
+            content_changed: true,
+            unmarked: false,
+        }
+    }
+
+    const WRITTEN: IpAddr = ip_macro::ip!("203.0.113.7");
+    const CHANGED: IpAddr = ip_macro::ip!("192.0.2.1");
+
+    #[tokio::test]
+    async fn overwrites_what_we_wrote() {
+        let cfg = config("refuse");
+        let ctx = crate::tests::context(&cfg, "outside-ours");
+        let zone = &cfg.zones()[0];
+
+        // nothing was published yet, so there's nothing to tell apart
+        assert!(ctx
+            .allow_overwrite(zone, IpFamily::V4, &update(CHANGED))
+            .await
+            .unwrap());
+
+        ctx.save_published(zone, IpFamily::V4, WRITTEN)
+            .await
+            .unwrap();
+        assert!(ctx
+            .allow_overwrite(zone, IpFamily::V4, &update(WRITTEN))
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn warns_and_overwrites_outside_changes() {
+        for mode in ["warn", "overwrite"] {
+            let cfg = config(mode);
+            let ctx = crate::tests::context(&cfg, &format!("outside-{mode}"));
+            let zone = &cfg.zones()[0];
+
+            ctx.save_published(zone, IpFamily::V4, WRITTEN)
+                .await
+                .unwrap();
+            assert!(ctx
+                .allow_overwrite(zone, IpFamily::V4, &update(CHANGED))
+                .await
+                .unwrap());
+        }
+    }
+
+    #[tokio::test]
+    async fn holds_outside_changes_until_acknowledged() {
+        let cfg = config("refuse");
+        let ctx = crate::tests::context(&cfg, "outside-refuse");
+        let zone = &cfg.zones()[0];
+        let key = Published::key(zone.record(), "A");
+
+        ctx.save_published(zone, IpFamily::V4, WRITTEN)
+            .await
+            .unwrap();
+        for _ in 0..2 {
+            assert!(!ctx
+                .allow_overwrite(zone, IpFamily::V4, &update(CHANGED))
+                .await
+                .unwrap());
+            assert_eq!(ctx.held.get(&key), Some(CHANGED));
+        }
+
+        state::acknowledge(&ctx.state_dir).unwrap();
+        assert!(ctx
+            .allow_overwrite(zone, IpFamily::V4, &update(CHANGED))
+            .await
+            .unwrap());
+        assert_eq!(ctx.held.get(&key), None);
+
+        // the acknowledgement is used up, a later change is held again
+        let again = ip_macro::ip!("192.0.2.9");
+        assert!(!ctx
+            .allow_overwrite(zone, IpFamily::V4, &update(again))
+            .await
+            .unwrap());
+    }
+}
@@ -1,4 +1,5 @@
 use crate::err;
+use std::path::Path;
 
 #[cfg(unix)]
 fn ensure_root() {
@@ -128,23 +129,49 @@ fn make_config() {
     inner().unwrap_or_else(|e| crate::abort!("{e}"));
 }
 
-pub fn pre_run() {
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum RunMode {
+    Normal,
+    Simulate,
+    /// restore the named snapshot, or the latest one, then exit
+    Rollback(Option<Box<str>>),
+}
+
+pub fn pre_run() -> RunMode {
     err::set_hook();
     #[cfg(target_os = "linux")]
     ensure_root();
 
     set_working_dir();
 
-    if 2 < std::env::args().count() {
-        panic!("expected at most one argument to be passed!")
+    // rollback is the only subcommand taking an argument, the snapshot to restore
+    let max_args = match std::env::args().nth(1).as_deref() {
+        Some("rollback") => 3,
+        _ => 2,
+    };
+    if max_args < std::env::args().count() {
+        panic!("expected at most {} arguments to be passed!", max_args - 1)
     }
 
     match std::env::args().nth(1).as_deref() {
         Some("add-to-startup") => add_to_startup(),
         Some("remove-from-startup") => remove_from_startup(),
         Some("make-config") => make_config(),
+        Some("acknowledge") => {
+            crate::state::acknowledge(Path::new(crate::state::DEFAULT_STATE_DIR))
+                .unwrap_or_else(|e| crate::abort!("{e}"))
+        }
+        Some("status") => crate::ha::show_status(Path::new(crate::state::DEFAULT_STATE_DIR))
+            .unwrap_or_else(|e| crate::abort!("{e:#}")),
+        Some("simulate") => {
+            crate::simulate::start();
+            return RunMode::Simulate;
+        }
+        Some("rollback") => {
+            return RunMode::Rollback(std::env::args().nth(2).map(String::into_boxed_str))
+        }
         Some(arg) => panic!("unexpected subcommand: {arg}"),
-        None => return,
+        None => return RunMode::Normal,
     }
 
     std::process::exit(0);
@@ -0,0 +1,127 @@
+use std::num::NonZeroU32;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::Instant;
+
+struct BucketState {
+    tokens: f64,
+    last_refill: Instant,
+    paused_until: Instant,
+}
+
+/// A token bucket holding up to `capacity` requests, refilled evenly over `period`
+pub struct TokenBucket {
+    capacity: f64,
+    per_token: Duration,
+    state: Mutex<BucketState>,
+}
+
+impl TokenBucket {
+    pub fn new(capacity: NonZeroU32, period: Duration) -> Self {
+        let now = Instant::now();
+        TokenBucket {
+            capacity: capacity.get() as f64,
+            per_token: period / capacity.get(),
+            state: Mutex::new(BucketState {
+                tokens: capacity.get() as f64,
+                last_refill: now,
+                paused_until: now,
+            }),
+        }
+    }
+
+    /// waits until a request is allowed to go through
+    pub async fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut state = self.state.lock().unwrap();
+                let now = Instant::now();
+
+                if now < state.paused_until {
+                    state.paused_until - now
+                } else {
+                    let refilled = if self.per_token.is_zero() {
+                        self.capacity
+                    } else {
+                        (now - state.last_refill).as_secs_f64() / self.per_token.as_secs_f64()
+                    };
+                    state.tokens = (state.tokens + refilled).min(self.capacity);
+                    state.last_refill = now;
+
+                    if state.tokens >= 1.0 {
+                        state.tokens -= 1.0;
+                        return;
+                    }
+
+                    self.per_token.mul_f64(1.0 - state.tokens)
+                }
+            };
+
+            tokio::time::sleep(wait).await
+        }
+    }
+
+    /// holds back every request for `duration`, used when the server tells us to back off
+    pub fn pause_for(&self, duration: Duration) {
+        // callers cap the delay, but a pause past what an instant can hold must not bring us down
+        let now = Instant::now();
+        let until = now
+            .checked_add(duration)
+            .unwrap_or_else(|| now + Duration::from_secs(5 * 60));
+        let mut state = self.state.lock().unwrap();
+        state.paused_until = state.paused_until.max(until);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::non_zero;
+
+    /// how long it takes to get `n` requests through
+    async fn time_for(bucket: &TokenBucket, n: usize) -> Duration {
+        let start = Instant::now();
+        for _ in 0..n {
+            bucket.acquire().await
+        }
+        start.elapsed()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn bursts_up_to_the_capacity() {
+        let bucket = TokenBucket::new(non_zero!(10), Duration::from_secs(10));
+        assert_eq!(time_for(&bucket, 10).await, Duration::ZERO);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn refills_evenly_over_the_period() {
+        let bucket = TokenBucket::new(non_zero!(10), Duration::from_secs(10));
+        time_for(&bucket, 10).await;
+
+        let waited = time_for(&bucket, 5).await;
+        assert!(
+            (Duration::from_secs(5)..Duration::from_millis(5100)).contains(&waited),
+            "waited {waited:?}"
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn holds_everything_back_while_paused() {
+        let bucket = TokenBucket::new(non_zero!(10), Duration::from_secs(10));
+        bucket.pause_for(Duration::from_secs(30));
+        // a shorter pause doesn't cut the longer one short
+        bucket.pause_for(Duration::from_secs(1));
+
+        let waited = time_for(&bucket, 1).await;
+        assert!(waited >= Duration::from_secs(30), "waited {waited:?}");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn survives_a_pause_too_long_to_hold() {
+        let bucket = TokenBucket::new(non_zero!(10), Duration::from_secs(10));
+        bucket.pause_for(Duration::MAX);
+
+        let waited = time_for(&bucket, 1).await;
+        assert!(waited >= Duration::from_secs(5 * 60), "waited {waited:?}");
+    }
+}
@@ -1,7 +1,9 @@
 use crate::abort_unreachable;
 use crate::config::Config;
-use reqwest::header::{HeaderName, HeaderValue, CONTENT_TYPE};
-use reqwest::{Body, Client, ClientBuilder, IntoUrl, Method, Request, Response};
+use crate::rate_limit::TokenBucket;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE, RETRY_AFTER};
+use reqwest::{Body, Client, ClientBuilder, IntoUrl, Method, Request, Response, StatusCode};
+use std::sync::Arc;
 use std::time::Duration;
 
 macro_rules! from_static {
@@ -55,6 +57,17 @@ pub struct RetryingClient {
     client: Client,
     max_retries: u8,
     retry_interval: Duration,
+    rate_limit: Option<Arc<TokenBucket>>,
+}
+
+/// the longest we wait before retrying, whatever the server or the backoff says
+const MAX_DELAY: Duration = Duration::from_secs(5 * 60);
+
+/// the `Retry-After` delay of a response, only the delay-seconds form is supported
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let secs = headers.get(RETRY_AFTER)?.to_str().ok()?;
+    let delay = secs.trim().parse().ok().map(Duration::from_secs)?;
+    Some(delay.min(MAX_DELAY))
 }
 
 impl RetryingClient {
@@ -88,20 +101,30 @@ impl RetryingClient {
                 client,
                 max_retries,
                 retry_interval,
+                rate_limit: None,
             })
             .unwrap_or_else(|e| abort_unreachable!("ClientBuilder failed {e}"))
     }
 
+    /// returns a client that sends every request, retries included, through `bucket`
+    pub fn with_rate_limit(&self, bucket: Arc<TokenBucket>) -> Self {
+        RetryingClient {
+            rate_limit: Some(bucket),
+            ..self.clone()
+        }
+    }
+
+    async fn acquire(&self) {
+        if let Some(bucket) = &self.rate_limit {
+            bucket.acquire().await
+        }
+    }
+
     /// See [`Client::get`]
     pub fn get<U: IntoUrl>(&self, url: U) -> RequestBuilder {
         self.request(Method::GET, url)
     }
 
-    /// See [`Client::patch`]
-    pub fn patch<U: IntoUrl>(&self, url: U) -> RequestBuilder {
-        self.request(Method::PATCH, url)
-    }
-
     /// See [`Client::request`]
     pub fn request<U: IntoUrl>(&self, method: Method, url: U) -> RequestBuilder {
         RequestBuilder {
@@ -119,16 +142,24 @@ impl RetryingClient {
             }
 
             if let Some(req) = req.try_clone() {
+                let backoff = || {
+                    self.retry_interval
+                        .checked_mul((i / 2).max(1) as u32)
+                        .map_or(MAX_DELAY, |delay| delay.min(MAX_DELAY))
+                };
+
+                self.acquire().await;
                 match self.client.execute(req).await {
-                    Ok(resp) => return Ok(resp),
-                    Err(_) => {
-                        let sleep_for = self
-                            .retry_interval
-                            .checked_mul((i / 2).max(1) as u32)
-                            .unwrap_or(Duration::MAX);
+                    Ok(resp) if resp.status() == StatusCode::TOO_MANY_REQUESTS => {
+                        let sleep_for = retry_after(resp.headers()).unwrap_or_else(backoff);
+                        if let Some(bucket) = &self.rate_limit {
+                            bucket.pause_for(sleep_for)
+                        }
 
                         tokio::time::sleep(sleep_for).await
                     }
+                    Ok(resp) => return Ok(resp),
+                    Err(_) => tokio::time::sleep(backoff()).await,
                 }
             } else {
                 abort_unreachable!("tried to use a streaming request");
@@ -137,6 +168,38 @@ impl RetryingClient {
             i += 1
         }
 
+        self.acquire().await;
         self.client.execute(req).await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(retry_after: &'static str) -> HeaderMap {
+        HeaderMap::from_iter([(RETRY_AFTER, HeaderValue::from_static(retry_after))])
+    }
+
+    #[test]
+    fn reads_delay_seconds() {
+        assert_eq!(retry_after(&headers("120")), Some(Duration::from_secs(120)));
+        assert_eq!(retry_after(&headers(" 5 ")), Some(Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn ignores_what_it_cant_read() {
+        assert_eq!(retry_after(&HeaderMap::new()), None);
+        assert_eq!(retry_after(&headers("Wed, 21 Oct 2015 07:28:00 GMT")), None);
+        assert_eq!(retry_after(&headers("-1")), None);
+    }
+
+    #[test]
+    fn caps_huge_delays() {
+        assert_eq!(retry_after(&headers("99999999999999")), Some(MAX_DELAY));
+        assert_eq!(
+            retry_after(&headers("18446744073709551615")),
+            Some(MAX_DELAY)
+        );
+    }
+}
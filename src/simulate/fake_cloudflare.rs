@@ -0,0 +1,732 @@
+use crate::config::api_fields::{ApiFields, RoundRobin, Zone};
+use crate::config::family::IpFamily;
+use crate::simulate::server::{Request, Response};
+use serde_json::{json, Map, Value};
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const API_PATH: &str = "/client/v4";
+
+/// the addresses the seeded records start with, so the first update has something to change
+fn stale_address(family: IpFamily) -> IpAddr {
+    match family {
+        IpFamily::V4 => ip_macro::ip!("192.0.2.1"),
+        IpFamily::V6 => ip_macro::ip!("2001:db8::1"),
+    }
+}
+
+fn sibling_address(family: IpFamily) -> IpAddr {
+    match family {
+        IpFamily::V4 => ip_macro::ip!("192.0.2.2"),
+        IpFamily::V6 => ip_macro::ip!("2001:db8::2"),
+    }
+}
+
+struct FakeZone {
+    id: Box<str>,
+    name: Box<str>,
+}
+
+struct FakeList {
+    account_id: Box<str>,
+    id: Box<str>,
+    name: Box<str>,
+    items: Vec<Map<String, Value>>,
+}
+
+/// An in memory stand in for the parts of the cloudflare api the daemon talks to
+pub struct FakeCloudflare {
+    zones: Vec<FakeZone>,
+    records: Mutex<Vec<Map<String, Value>>>,
+    lists: Mutex<Vec<FakeList>>,
+    next_id: AtomicU64,
+}
+
+/// renders strings without their json quotes, for logging
+fn show(value: &Value) -> String {
+    match value {
+        Value::String(str) => str.clone(),
+        value => value.to_string(),
+    }
+}
+
+/// the current time the way cloudflare writes it, `2014-01-01T05:20:00.123456Z`
+fn created_on() -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let (days, secs) = (now.as_secs() / 86400, now.as_secs() % 86400);
+
+    // days since the epoch to a civil date, http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z / 146097;
+    let doe = z % 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:06}Z",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60,
+        now.subsec_micros()
+    )
+}
+
+fn success(result: Value) -> Response {
+    Response::json(
+        200,
+        &json!({ "success": true, "errors": [], "messages": [], "result": result }),
+    )
+}
+
+fn list(items: Vec<Value>) -> Response {
+    let count = items.len();
+    Response::json(
+        200,
+        &json!({
+            "success": true,
+            "errors": [],
+            "messages": [],
+            "result": items,
+            "result_info": { "page": 1, "per_page": count.max(1), "total_pages": 1, "count": count, "total_count": count }
+        }),
+    )
+}
+
+fn list_cursor(items: Vec<Value>) -> Response {
+    Response::json(
+        200,
+        &json!({
+            "success": true,
+            "errors": [],
+            "messages": [],
+            "result": items,
+            "result_info": { "cursors": {} }
+        }),
+    )
+}
+
+pub fn failure(status: u16, code: u32, message: &str) -> Response {
+    Response::json(
+        status,
+        &json!({
+            "success": false,
+            "errors": [{ "code": code, "message": message }],
+            "messages": [],
+            "result": null
+        }),
+    )
+}
+
+impl FakeCloudflare {
+    /// creates a zone for every configured record, and seeds it with a stale record
+    /// unless the record is meant to be created by the daemon
+    pub fn seed(api: &ApiFields) -> Self {
+        let fake = FakeCloudflare {
+            zones: vec![],
+            records: Mutex::new(vec![]),
+            lists: Mutex::new(vec![]),
+            next_id: AtomicU64::new(1),
+        };
+        let mut zones = Vec::<FakeZone>::new();
+        let mut records = vec![];
+
+        let mut zone_id = |zone: &Zone| {
+            let name = zone.name().map(Box::from).unwrap_or_else(|| {
+                let labels = zone.record().rsplitn(3, '.').collect::<Vec<_>>();
+                match &*labels {
+                    [tld, domain, _] => format!("{domain}.{tld}").into_boxed_str(),
+                    _ => Box::from(zone.record()),
+                }
+            });
+
+            match zones.iter().find(|fake| fake.name == name) {
+                Some(fake) => fake.id.clone(),
+                None => {
+                    let id = zone
+                        .id()
+                        .map(Box::from)
+                        .unwrap_or_else(|| fake.new_id().into_boxed_str());
+                    zones.push(FakeZone {
+                        id: id.clone(),
+                        name,
+                    });
+                    id
+                }
+            }
+        };
+
+        for zone in api.zones.iter() {
+            let zone_id = zone_id(zone);
+
+            for family in zone.family().iter() {
+                let mut record = |ip: IpAddr, comment: Option<&str>, tags: Vec<&str>| {
+                    let mut record = fake.new_record(&zone_id, zone.record(), family, ip);
+                    record.insert("comment".into(), json!(comment));
+                    record.insert("tags".into(), json!(tags));
+                    records.push(record);
+                };
+
+                if zone.round_robin().is_some() {
+                    record(sibling_address(family), Some("another site"), vec![]);
+                }
+
+                if zone.create_if_missing() {
+                    continue;
+                }
+
+                match zone.round_robin() {
+                    Some(RoundRobin::Comment(comment)) => {
+                        record(stale_address(family), Some(comment), vec![])
+                    }
+                    Some(RoundRobin::Tag(tag)) => record(stale_address(family), None, vec![tag]),
+                    Some(RoundRobin::LastIp) | None => record(stale_address(family), None, vec![]),
+                }
+            }
+
+            if zone.svcb_hints() {
+                let hints = zone
+                    .family()
+                    .iter()
+                    .map(|family| {
+                        let key = match family {
+                            IpFamily::V4 => "ipv4hint",
+                            IpFamily::V6 => "ipv6hint",
+                        };
+                        format!("{key}=\"{}\"", stale_address(family))
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                let value = format!("alpn=\"h3,h2\" {hints}");
+
+                let Value::Object(record) = json!({
+                    "id": fake.new_id(),
+                    "zone_id": zone_id,
+                    "name": zone.record(),
+                    "type": "HTTPS",
+                    "content": format!("1 . {value}"),
+                    "data": { "priority": 1, "target": ".", "value": value },
+                    "proxied": false,
+                    "ttl": 1,
+                    "comment": null,
+                    "tags": [],
+                }) else {
+                    unreachable!()
+                };
+                records.push(record);
+            }
+        }
+
+        // declared CNAMEs start out pointing somewhere else, the other declared records are missing
+        for record in api.records.iter() {
+            let zone_id = zone_id(record.zone());
+            if record.record_type() == "CNAME" {
+                let Value::Object(record) = json!({
+                    "id": fake.new_id(),
+                    "zone_id": zone_id,
+                    "name": record.name(),
+                    "type": "CNAME",
+                    "content": "stale.example.net",
+                    "proxied": false,
+                    "ttl": 1,
+                    "comment": null,
+                    "tags": [],
+                }) else {
+                    unreachable!()
+                };
+                records.push(record);
+            }
+        }
+
+        // another instance holds the lease for a little while, so the takeover can be watched
+        if let Some(ha) = &api.ha {
+            let zone_id = zone_id(ha.zone());
+            let expires = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs()
+                + 10;
+            let Value::Object(record) = json!({
+                "id": fake.new_id(),
+                "zone_id": zone_id,
+                "name": ha.record(),
+                "type": "TXT",
+                "content": format!("\"owner=simulated-peer expires={expires}\""),
+                "created_on": created_on(),
+                "proxied": false,
+                "ttl": 1,
+                "comment": null,
+                "tags": [],
+            }) else {
+                unreachable!()
+            };
+            records.push(record);
+        }
+
+        // every list starts with someone else's entry that must be left alone
+        let mut lists = Vec::<FakeList>::new();
+        for list in api.ip_lists.iter() {
+            if lists
+                .iter()
+                .any(|fake| *fake.account_id == *list.account_id() && *fake.name == *list.name())
+            {
+                continue;
+            }
+
+            let mut items = vec![fake.new_list_item("198.51.100.10", Some("another office"))];
+            if let Some(comment) = list.comment() {
+                for family in list.family().iter() {
+                    items
+                        .push(fake.new_list_item(&stale_address(family).to_string(), Some(comment)))
+                }
+            }
+
+            lists.push(FakeList {
+                account_id: Box::from(list.account_id()),
+                id: fake.new_id().into_boxed_str(),
+                name: Box::from(list.name()),
+                items,
+            })
+        }
+
+        FakeCloudflare {
+            zones,
+            records: Mutex::new(records),
+            lists: Mutex::new(lists),
+            next_id: fake.next_id,
+        }
+    }
+
+    fn new_list_item(&self, ip: &str, comment: Option<&str>) -> Map<String, Value> {
+        let Value::Object(item) = json!({ "id": self.new_id(), "ip": ip, "comment": comment })
+        else {
+            unreachable!()
+        };
+        item
+    }
+
+    fn new_id(&self) -> String {
+        format!("{:032x}", self.next_id.fetch_add(1, Ordering::Relaxed))
+    }
+
+    fn new_record(
+        &self,
+        zone_id: &str,
+        name: &str,
+        family: IpFamily,
+        ip: IpAddr,
+    ) -> Map<String, Value> {
+        let Value::Object(record) = json!({
+            "id": self.new_id(),
+            "zone_id": zone_id,
+            "name": name,
+            "type": family.record_type(),
+            "content": ip.to_string(),
+            "proxied": false,
+            "ttl": 1,
+            "comment": null,
+            "tags": [],
+        }) else {
+            unreachable!()
+        };
+        record
+    }
+
+    /// the addresses the records with this name and type currently hold
+    pub fn addresses(&self, name: &str, record_type: &str) -> Vec<IpAddr> {
+        self.records
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|record| record["name"] == *name && record["type"] == *record_type)
+            .filter_map(|record| record["content"].as_str()?.parse().ok())
+            .collect()
+    }
+
+    /// what the records with this name and type currently hold
+    #[cfg(test)]
+    pub fn contents(&self, name: &str, record_type: &str) -> Vec<String> {
+        self.records
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|record| record["name"] == *name && record["type"] == *record_type)
+            .map(|record| show(&record["content"]))
+            .collect()
+    }
+
+    /// the addresses in the ip list with this name, in the order they were added
+    #[cfg(test)]
+    pub fn list_items(&self, name: &str) -> Vec<IpAddr> {
+        self.lists
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|list| *list.name == *name)
+            .flat_map(|list| &list.items)
+            .filter_map(|item| item["ip"].as_str()?.parse().ok())
+            .collect()
+    }
+
+    pub fn describe(&self) -> String {
+        let records = self.records.lock().unwrap();
+        let mut out = String::new();
+        for zone in &self.zones {
+            out += &format!("  zone {} ({})\n", zone.name, zone.id);
+            for record in records.iter().filter(|r| r["zone_id"] == *zone.id) {
+                out += &format!(
+                    "    {} {} {}\n",
+                    record["type"].as_str().unwrap_or_default(),
+                    record["name"].as_str().unwrap_or_default(),
+                    record["content"].as_str().unwrap_or_default()
+                );
+            }
+        }
+        for list in self.lists.lock().unwrap().iter() {
+            out += &format!("  ip list {} ({})\n", list.name, list.account_id);
+            for item in &list.items {
+                out += &format!("    {}\n", show(&item["ip"]));
+            }
+        }
+        out
+    }
+
+    pub fn handle(&self, request: Request) -> Response {
+        println!("[fake cloudflare] {} {}", request.method, request.path);
+
+        let Some(path) = request.path.strip_prefix(API_PATH) else {
+            return failure(404, 7000, "No route for that URI");
+        };
+        let segments = path.trim_matches('/').split('/').collect::<Vec<_>>();
+
+        match (&*request.method, &*segments) {
+            ("GET", ["zones"]) => list(
+                self.zones
+                    .iter()
+                    .filter(|zone| request.query("name").is_none_or(|name| *zone.name == *name))
+                    .map(|zone| json!({ "id": zone.id, "name": zone.name, "status": "active" }))
+                    .collect(),
+            ),
+            ("GET", ["user", "tokens", "verify"]) => success(json!({
+                "id": "simulated",
+                "status": "active",
+                "expires_on": null
+            })),
+            ("GET", ["zones", zone_id]) => {
+                match self.zones.iter().find(|zone| *zone.id == **zone_id) {
+                    Some(zone) => success(json!({
+                        "id": zone.id,
+                        "name": zone.name,
+                        "status": "active",
+                        "permissions": ["#zone:read", "#dns_records:read", "#dns_records:edit"]
+                    })),
+                    None => failure(
+                        404,
+                        7003,
+                        "Could not route to /zones, perhaps your object identifier is invalid?",
+                    ),
+                }
+            }
+            ("GET", ["accounts", account_id, "rules", "lists"]) => list(
+                self.lists
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .filter(|list| *list.account_id == **account_id)
+                    .map(|list| {
+                        json!({
+                            "id": list.id,
+                            "name": list.name,
+                            "kind": "ip",
+                            "num_items": list.items.len()
+                        })
+                    })
+                    .collect(),
+            ),
+            ("GET", ["accounts", _, "rules", "lists", "bulk_operations", operation_id]) => {
+                // every change is applied right away, so every operation is already done
+                success(json!({ "id": operation_id, "status": "completed" }))
+            }
+            (method, ["accounts", account_id, "rules", "lists", list_id, "items"]) => {
+                self.handle_list_items(method, account_id, list_id, &request)
+            }
+            (method, ["zones", zone_id, "dns_records", rest @ ..]) => {
+                if !self.zones.iter().any(|zone| *zone.id == **zone_id) {
+                    return failure(
+                        404,
+                        7003,
+                        "Could not route to /zones, perhaps your object identifier is invalid?",
+                    );
+                }
+                self.handle_records(method, zone_id, rest, &request)
+            }
+            _ => failure(404, 7000, "No route for that URI"),
+        }
+    }
+
+    fn create(&self, zone_id: &str, body: Map<String, Value>) -> Map<String, Value> {
+        let mut record = Map::new();
+        record.insert("id".into(), json!(self.new_id()));
+        record.insert("zone_id".into(), json!(zone_id));
+        record.insert("proxied".into(), json!(false));
+        record.insert("ttl".into(), json!(1));
+        record.insert("comment".into(), Value::Null);
+        record.insert("tags".into(), json!([]));
+        record.insert("created_on".into(), json!(created_on()));
+        record.extend(body);
+
+        println!(
+            "[fake cloudflare] created {} {} {}",
+            show(&record["type"]),
+            show(&record["name"]),
+            show(&record["content"])
+        );
+        record
+    }
+
+    fn patch(record: &mut Map<String, Value>, body: Map<String, Value>) -> Map<String, Value> {
+        for (key, value) in body {
+            if key != "id" && record.get(&key) != Some(&value) {
+                println!(
+                    "[fake cloudflare] {} {key}: {} -> {}",
+                    show(&record["name"]),
+                    show(record.get(&key).unwrap_or(&Value::Null)),
+                    show(&value)
+                );
+                record.insert(key, value);
+            }
+        }
+        record.clone()
+    }
+
+    fn handle_list_items(
+        &self,
+        method: &str,
+        account_id: &str,
+        list_id: &str,
+        request: &Request,
+    ) -> Response {
+        let mut lists = self.lists.lock().unwrap();
+        let Some(list) = lists
+            .iter_mut()
+            .find(|list| *list.account_id == *account_id && *list.id == *list_id)
+        else {
+            return failure(404, 10000, "list not found");
+        };
+
+        let body = || match serde_json::from_slice::<Value>(&request.body) {
+            Ok(body) => Ok(body),
+            Err(err) => Err(failure(
+                400,
+                10026,
+                &format!("Request body is invalid: {err}"),
+            )),
+        };
+
+        let operation = || success(json!({ "operation_id": self.new_id() }));
+
+        match method {
+            "GET" => list_cursor(list.items.iter().cloned().map(Value::Object).collect()),
+            "POST" => {
+                let body = match body() {
+                    Ok(body) => body,
+                    Err(response) => return response,
+                };
+                for item in body.as_array().into_iter().flatten() {
+                    println!(
+                        "[fake cloudflare] {} added {}",
+                        list.name,
+                        show(&item["ip"])
+                    );
+                    list.items
+                        .push(self.new_list_item(&show(&item["ip"]), item["comment"].as_str()));
+                }
+                operation()
+            }
+            "DELETE" => {
+                let body = match body() {
+                    Ok(body) => body,
+                    Err(response) => return response,
+                };
+                let ids = body["items"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .map(|item| &item["id"])
+                    .collect::<Vec<_>>();
+                list.items.retain(|item| {
+                    let remove = ids.contains(&&item["id"]);
+                    if remove {
+                        println!(
+                            "[fake cloudflare] {} removed {}",
+                            list.name,
+                            show(&item["ip"])
+                        );
+                    }
+                    !remove
+                });
+                operation()
+            }
+            _ => failure(405, 10000, "Method not allowed"),
+        }
+    }
+
+    fn handle_records(
+        &self,
+        method: &str,
+        zone_id: &str,
+        rest: &[&str],
+        request: &Request,
+    ) -> Response {
+        let mut records = self.records.lock().unwrap();
+        let in_zone = |record: &Map<String, Value>| record["zone_id"] == *zone_id;
+
+        let body = || match serde_json::from_slice::<Map<String, Value>>(&request.body) {
+            Ok(body) => Ok(body),
+            Err(err) => Err(failure(
+                400,
+                9207,
+                &format!("Request body is invalid: {err}"),
+            )),
+        };
+
+        match (method, rest) {
+            ("GET", []) => list(
+                records
+                    .iter()
+                    .filter(|record| in_zone(record))
+                    .filter(|record| {
+                        ["type", "name"].iter().all(|key| {
+                            request
+                                .query(key)
+                                .is_none_or(|value| record[*key] == *value)
+                        })
+                    })
+                    .cloned()
+                    .map(Value::Object)
+                    .collect(),
+            ),
+            ("POST", []) => {
+                let body = match body() {
+                    Ok(body) => body,
+                    Err(response) => return response,
+                };
+
+                let record = self.create(zone_id, body);
+                records.push(record.clone());
+                success(Value::Object(record))
+            }
+            ("POST", ["batch"]) => {
+                let body = match body() {
+                    Ok(body) => body,
+                    Err(response) => return response,
+                };
+
+                let entries = |key: &str| -> Vec<Map<String, Value>> {
+                    body.get(key)
+                        .and_then(Value::as_array)
+                        .into_iter()
+                        .flatten()
+                        .filter_map(Value::as_object)
+                        .cloned()
+                        .collect()
+                };
+                let (deletes, patches, posts) =
+                    (entries("deletes"), entries("patches"), entries("posts"));
+
+                // the batch is all or nothing, so check every delete and patch before applying any of them
+                for delete in &deletes {
+                    if !records
+                        .iter()
+                        .any(|record| in_zone(record) && Some(&record["id"]) == delete.get("id"))
+                    {
+                        return failure(404, 81044, "Record does not exist.");
+                    }
+                }
+
+                let mut positions = vec![];
+                for patch in &patches {
+                    match records.iter().position(|record| {
+                        in_zone(record) && Some(&record["id"]) == patch.get("id")
+                    }) {
+                        Some(pos) => positions.push(pos),
+                        None => return failure(404, 81044, "Record does not exist."),
+                    }
+                }
+
+                println!(
+                    "[fake cloudflare] batch of {} deletes, {} patches and {} posts",
+                    deletes.len(),
+                    patches.len(),
+                    posts.len()
+                );
+                let patched = patches
+                    .into_iter()
+                    .zip(positions)
+                    .map(|(patch, pos)| Value::Object(Self::patch(&mut records[pos], patch)))
+                    .collect::<Vec<_>>();
+                let posted = posts
+                    .into_iter()
+                    .map(|post| {
+                        let record = self.create(zone_id, post);
+                        records.push(record.clone());
+                        Value::Object(record)
+                    })
+                    .collect::<Vec<_>>();
+
+                // deletes go last, so they don't shift the positions of the patched records
+                let deleted = deletes
+                    .iter()
+                    .map(|delete| {
+                        let pos = records
+                            .iter()
+                            .position(|record| {
+                                in_zone(record) && Some(&record["id"]) == delete.get("id")
+                            })
+                            .expect("checked above");
+                        json!({ "id": records.remove(pos)["id"] })
+                    })
+                    .collect::<Vec<_>>();
+
+                success(json!({ "deletes": deleted, "patches": patched, "posts": posted }))
+            }
+            (method, [record_id]) => {
+                let Some(pos) = records
+                    .iter()
+                    .position(|record| in_zone(record) && record["id"] == **record_id)
+                else {
+                    return failure(404, 81044, "Record does not exist.");
+                };
+
+                match method {
+                    "GET" => success(Value::Object(records[pos].clone())),
+                    "PATCH" => {
+                        let body = match body() {
+                            Ok(body) => body,
+                            Err(response) => return response,
+                        };
+                        success(Value::Object(Self::patch(&mut records[pos], body)))
+                    }
+                    "DELETE" => {
+                        let record = records.remove(pos);
+                        println!(
+                            "[fake cloudflare] deleted {} {}",
+                            show(&record["type"]),
+                            show(&record["name"])
+                        );
+                        success(json!({ "id": record["id"] }))
+                    }
+                    _ => failure(405, 10000, "Method not allowed"),
+                }
+            }
+            _ => failure(404, 7000, "No route for that URI"),
+        }
+    }
+}
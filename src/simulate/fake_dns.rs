@@ -0,0 +1,66 @@
+use crate::simulate::fake_cloudflare::FakeCloudflare;
+use hickory_proto::op::{Message, MessageType, ResponseCode};
+use hickory_proto::rr::rdata::{A, AAAA};
+use hickory_proto::rr::{RData, Record, RecordType};
+use hickory_proto::serialize::binary::{BinDecodable, BinEncodable};
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use tokio::net::UdpSocket;
+
+fn answer(fake: &FakeCloudflare, request: &Message) -> Message {
+    let mut response = Message::new();
+    response
+        .set_id(request.id())
+        .set_message_type(MessageType::Response)
+        .set_op_code(request.op_code())
+        .set_authoritative(true)
+        .add_queries(request.queries().to_vec());
+
+    for query in request.queries() {
+        let name = query.name().to_ascii();
+        let name = name.trim_end_matches('.');
+        let record_type = match query.query_type() {
+            RecordType::A => "A",
+            RecordType::AAAA => "AAAA",
+            _ => continue,
+        };
+
+        println!("[fake dns] {record_type} {name}");
+        for ip in fake.addresses(name, record_type) {
+            let data = match ip {
+                IpAddr::V4(ip) => RData::A(A(ip)),
+                IpAddr::V6(ip) => RData::AAAA(AAAA(ip)),
+            };
+            response.add_answer(Record::from_rdata(query.name().clone(), 60, data));
+        }
+    }
+
+    if response.answers().is_empty() {
+        response.set_response_code(ResponseCode::NXDomain);
+    }
+    response
+}
+
+/// an authoritative nameserver on localhost, serving the records of the fake api
+pub async fn serve(fake: Arc<FakeCloudflare>) -> io::Result<SocketAddr> {
+    let socket = UdpSocket::bind((std::net::Ipv4Addr::LOCALHOST, 0)).await?;
+    let addr = socket.local_addr()?;
+
+    tokio::spawn(async move {
+        let mut buf = [0; 512];
+        loop {
+            let Ok((len, peer)) = socket.recv_from(&mut buf).await else {
+                continue;
+            };
+            let Ok(request) = Message::from_bytes(&buf[..len]) else {
+                continue;
+            };
+            if let Ok(bytes) = answer(&fake, &request).to_bytes() {
+                let _ = socket.send_to(&bytes, peer).await;
+            }
+        }
+    });
+
+    Ok(addr)
+}
@@ -0,0 +1,183 @@
+use crate::config::api_fields::ApiFields;
+use crate::config::family::IpFamily;
+use crate::simulate::fake_cloudflare::API_PATH;
+use crate::simulate::server::Response;
+use crate::util::GLOBAL_TOKIO_RUNTIME;
+use anyhow::{Context, Result};
+use std::fs;
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::Arc;
+
+mod fake_cloudflare;
+mod fake_dns;
+mod server;
+
+pub(crate) use fake_cloudflare::FakeCloudflare;
+
+const SIMULATION_DIR: &str = "./simulation";
+
+/// the addresses the fake ip sources hand out
+fn simulated_address(family: IpFamily) -> IpAddr {
+    match family {
+        IpFamily::V4 => ip_macro::ip!("203.0.113.7"),
+        IpFamily::V6 => ip_macro::ip!("2001:db8::7"),
+    }
+}
+
+fn family_path(family: IpFamily) -> &'static str {
+    match family {
+        IpFamily::V4 => "/v4",
+        IpFamily::V6 => "/v6",
+    }
+}
+
+fn read_config(name: &str, default: &'static str) -> Result<String> {
+    let path = Path::new("./config").join(name);
+    match fs::read_to_string(&path) {
+        Ok(text) => Ok(text),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(default.to_owned()),
+        Err(err) => Err(err).with_context(|| format!("unable to read {}", path.display())),
+    }
+}
+
+fn setup() -> Result<()> {
+    let mut api = toml::from_str::<toml::Table>(
+        &fs::read_to_string("./config/api.toml")
+            .context("simulate needs a ./config/api.toml to simulate, run make-config first")?,
+    )
+    .context("invalid ./config/api.toml")?;
+
+    let http = read_config("http.toml", include_str!("../../includes/http.toml"))?;
+    let misc = read_config("misc.toml", include_str!("../../includes/misc.toml"))?;
+
+    let (api_addr, ip_addr, dns_addr, fake) = GLOBAL_TOKIO_RUNTIME.block_on(async {
+        let ip_addr = server::serve(|request| {
+            match [IpFamily::V4, IpFamily::V6]
+                .into_iter()
+                .find(|&family| *request.path == *family_path(family))
+            {
+                Some(family) => Response::text(200, simulated_address(family).to_string()),
+                None => Response::text(404, "not found"),
+            }
+        })
+        .await?;
+
+        // point the copied config at the fake api before validating it,
+        // so a bad api-base in the real config doesn't matter here
+        api.remove("api_base");
+        api.insert(
+            "api-base".into(),
+            format!("http://127.0.0.1{API_PATH}").into(),
+        );
+        let fields = toml::from_str::<ApiFields>(&toml::to_string(&api)?)
+            .context("invalid ./config/api.toml")?;
+
+        let fake = Arc::new(FakeCloudflare::seed(&fields));
+        let api_addr = server::serve({
+            let fake = Arc::clone(&fake);
+            move |request| fake.handle(request)
+        })
+        .await?;
+
+        let dns_addr = fake_dns::serve(Arc::clone(&fake)).await?;
+
+        anyhow::Ok((api_addr, ip_addr, dns_addr, fake))
+    })?;
+
+    api.insert(
+        "api-base".into(),
+        format!("http://{api_addr}{API_PATH}").into(),
+    );
+    for table in ["verify", "detect", "change-detection"] {
+        if let Some(toml::Value::Table(table)) = api.get_mut(table) {
+            table.insert(
+                "nameservers".into(),
+                toml::Value::Array(vec![dns_addr.to_string().into()]),
+            );
+        }
+    }
+
+    let sources = [IpFamily::V4, IpFamily::V6]
+        .into_iter()
+        .map(|family| {
+            format!(
+                "[\"http://{ip_addr}{path}\"]\nfamily = \"{family}\"\nsteps = [\"Plaintext\"]\n",
+                path = family_path(family),
+                family = match family {
+                    IpFamily::V4 => "v4",
+                    IpFamily::V6 => "v6",
+                }
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let config_dir = Path::new(SIMULATION_DIR).join("config");
+    fs::create_dir_all(&config_dir)?;
+    fs::write(config_dir.join("api.toml"), toml::to_string(&api)?)?;
+    fs::write(config_dir.join("http.toml"), http)?;
+    fs::write(config_dir.join("misc.toml"), misc)?;
+    fs::write(config_dir.join("sources.toml"), sources)?;
+
+    std::env::set_current_dir(SIMULATION_DIR)?;
+
+    println!("simulating against a fake cloudflare api at http://{api_addr}{API_PATH}");
+    println!(
+        "fake ip sources hand out {} and {}",
+        simulated_address(IpFamily::V4),
+        simulated_address(IpFamily::V6)
+    );
+    println!("a fake nameserver serves the records at {dns_addr}");
+    print!("seeded:\n{}", fake.describe());
+    Ok(())
+}
+
+/// sets up a fake cloudflare api and fake ip sources, and moves into a scratch
+/// directory with a copy of the config pointed at them,
+/// so a config can be tried out without touching real dns records
+pub fn start() {
+    setup().unwrap_or_else(|e| crate::abort!("unable to start the simulation: {e:#}"))
+}
+
+/// serves a fake cloudflare api and nameserver seeded from the `api` config, and returns the config pointed at them,
+/// the requests to the paths `reject` picks are turned down, for the tests to see how the engine copes
+#[cfg(test)]
+pub(crate) async fn fake_api(
+    api: &str,
+    reject: impl Fn(&str) -> bool + Send + Sync + 'static,
+) -> Result<(ApiFields, Arc<FakeCloudflare>)> {
+    let mut api = toml::from_str::<toml::Table>(api)?;
+    api.insert(
+        "api-base".into(),
+        format!("http://127.0.0.1{API_PATH}").into(),
+    );
+    let fake = Arc::new(FakeCloudflare::seed(&toml::from_str(&toml::to_string(
+        &api,
+    )?)?));
+
+    let api_addr = server::serve({
+        let fake = Arc::clone(&fake);
+        move |request| match reject(&request.path) {
+            true => fake_cloudflare::failure(400, 1004, "DNS Validation Error"),
+            false => fake.handle(request),
+        }
+    })
+    .await?;
+
+    let dns_addr = fake_dns::serve(Arc::clone(&fake)).await?;
+
+    api.insert(
+        "api-base".into(),
+        format!("http://{api_addr}{API_PATH}").into(),
+    );
+    for table in ["verify", "detect", "change-detection"] {
+        if let Some(toml::Value::Table(table)) = api.get_mut(table) {
+            table.insert(
+                "nameservers".into(),
+                toml::Value::Array(vec![dns_addr.to_string().into()]),
+            );
+        }
+    }
+    Ok((toml::from_str(&toml::to_string(&api)?)?, fake))
+}
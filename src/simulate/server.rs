@@ -0,0 +1,143 @@
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+/// just enough of an http/1.1 request for the fake services
+#[derive(Debug)]
+pub struct Request {
+    pub method: Box<str>,
+    pub path: Box<str>,
+    pub query: Vec<(Box<str>, Box<str>)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    pub fn query(&self, key: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(k, _)| &**k == key)
+            .map(|(_, v)| &**v)
+    }
+}
+
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn json(status: u16, value: &serde_json::Value) -> Self {
+        Response {
+            status,
+            content_type: "application/json",
+            body: value.to_string().into_bytes(),
+        }
+    }
+
+    pub fn text(status: u16, text: impl Into<String>) -> Self {
+        Response {
+            status,
+            content_type: "text/plain",
+            body: text.into().into_bytes(),
+        }
+    }
+}
+
+async fn read_request(stream: &mut BufReader<TcpStream>) -> io::Result<Option<Request>> {
+    let mut line = String::new();
+    if stream.read_line(&mut line).await? == 0 {
+        return Ok(None);
+    }
+
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(io::Error::other("malformed request line"));
+    };
+
+    let url = url::Url::parse("http://localhost")
+        .and_then(|base| base.join(target))
+        .map_err(io::Error::other)?;
+    let method = Box::<str>::from(method);
+
+    let mut content_length = 0;
+    loop {
+        line.clear();
+        stream.read_line(&mut line).await?;
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().map_err(io::Error::other)?;
+            }
+        }
+    }
+
+    let mut body = vec![0; content_length];
+    stream.read_exact(&mut body).await?;
+
+    Ok(Some(Request {
+        method,
+        path: url.path().into(),
+        query: url
+            .query_pairs()
+            .map(|(k, v)| (k.into(), v.into()))
+            .collect(),
+        body,
+    }))
+}
+
+async fn handle_connection<F>(stream: TcpStream, handler: Arc<F>) -> io::Result<()>
+where
+    F: Fn(Request) -> Response,
+{
+    let mut stream = BufReader::new(stream);
+    let Some(request) = read_request(&mut stream).await? else {
+        return Ok(());
+    };
+
+    let Response {
+        status,
+        content_type,
+        body,
+    } = handler(request);
+
+    let head = format!(
+        "HTTP/1.1 {status} {reason}\r\ncontent-type: {content_type}\r\ncontent-length: {len}\r\nconnection: close\r\n\r\n",
+        reason = reqwest::StatusCode::from_u16(status)
+            .ok()
+            .and_then(|status| status.canonical_reason())
+            .unwrap_or(""),
+        len = body.len()
+    );
+
+    let stream = stream.get_mut();
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(&body).await?;
+    stream.shutdown().await
+}
+
+/// binds to a random port on localhost and serves every connection with `handler`
+pub async fn serve<F>(handler: F) -> io::Result<SocketAddr>
+where
+    F: Fn(Request) -> Response + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind((std::net::Ipv4Addr::LOCALHOST, 0)).await?;
+    let addr = listener.local_addr()?;
+    let handler = Arc::new(handler);
+
+    tokio::spawn(async move {
+        loop {
+            let Ok((stream, _)) = listener.accept().await else {
+                continue;
+            };
+            tokio::spawn(handle_connection(stream, Arc::clone(&handler)));
+        }
+    });
+
+    Ok(addr)
+}
@@ -0,0 +1,404 @@
+use crate::cloudflare::{ApiError, RecordBody, SvcbData};
+use crate::config::api_fields::{Account, Zone};
+use crate::config::family::IpFamily;
+use crate::config::Config;
+use crate::state::Published;
+use crate::{dbg_println, DdnsContext};
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::io::ErrorKind;
+use std::net::IpAddr;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// under the state directory
+const SNAPSHOT_DIR: &str = "snapshots";
+
+/// a record as it was before we first changed it, holding everything needed to put it back
+#[derive(Debug, Serialize, Deserialize)]
+struct SavedRecord {
+    #[serde(default)]
+    zone_id: Box<str>,
+    id: Box<str>,
+    name: Box<str>,
+    #[serde(rename = "type")]
+    record_type: Box<str>,
+    #[serde(default)]
+    content: Option<Box<str>>,
+    #[serde(default)]
+    proxied: bool,
+    ttl: u32,
+    #[serde(default)]
+    comment: Option<Box<str>>,
+    #[serde(default)]
+    tags: Vec<Box<str>>,
+    #[serde(default)]
+    priority: Option<u16>,
+    /// only HTTPS and SVCB records carry data we know how to send back
+    #[serde(default)]
+    data: Option<serde_json::Value>,
+    /// the fingerprint of the account the record was read through, missing from older snapshots
+    #[serde(default)]
+    account: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Snapshot {
+    taken: u64,
+    records: Vec<SavedRecord>,
+}
+
+impl SavedRecord {
+    fn body<'a>(&'a self, data: Option<&'a SvcbData>) -> RecordBody<'a> {
+        RecordBody {
+            record_type: &self.record_type,
+            name: &self.name,
+            // structured records are restored through their data, plain ones through the content
+            content: match data {
+                Some(_) => None,
+                None => self.content.as_deref().map(Cow::Borrowed),
+            },
+            proxied: Some(self.proxied),
+            ttl: Some(self.ttl),
+            comment: self.comment.as_deref(),
+            tags: Some(&self.tags),
+            priority: self.priority,
+            data,
+        }
+    }
+}
+
+/// snapshots are named after the second they were taken in,
+/// with a counter after it when more than one was taken in that second
+fn snapshot_order(name: &str) -> (u64, u64) {
+    let (taken, count) = name.split_once('-').unwrap_or((name, "0"));
+    (taken.parse().unwrap_or(0), count.parse().unwrap_or(0))
+}
+
+/// the account the record was read through when the snapshot was taken
+///
+/// older snapshots don't say, so the account is looked up by the record's name, falling back to the top level one
+/// for the records that aren't in the config as such, like heartbeats
+fn account_for<'a>(cfg: &'a Config, record: &SavedRecord) -> Option<&'a Account> {
+    if let Some(fingerprint) = record.account {
+        return cfg
+            .account_by_fingerprint(fingerprint)
+            .map(|account| &**account);
+    }
+
+    cfg.zones()
+        .iter()
+        .find(|zone| zone.record() == &*record.name)
+        .or_else(|| {
+            cfg.records()
+                .iter()
+                .find(|r| r.name() == &*record.name && r.record_type() == &*record.record_type)
+                .map(|r| r.zone())
+        })
+        .map(Zone::account)
+        .or_else(|| cfg.account().map(|account| &**account))
+}
+
+async fn read_snapshot(dir: &Path, name: Option<&str>) -> Result<(Box<str>, Snapshot)> {
+    let shown = dir.display();
+    let mut names = vec![];
+    let mut entries = tokio::fs::read_dir(dir)
+        .await
+        .with_context(|| format!("there are no snapshots in {shown}"))?;
+    while let Some(entry) = entries.next_entry().await? {
+        let file_name = entry.file_name();
+        if let Some(stem) = file_name
+            .to_str()
+            .and_then(|name| name.strip_suffix(".json"))
+        {
+            names.push(Box::<str>::from(stem));
+        }
+    }
+    names.sort_by_key(|name| snapshot_order(name));
+
+    let name = match name {
+        Some(name) => {
+            let name = name.strip_suffix(".json").unwrap_or(name);
+            names
+                .into_iter()
+                .find(|known| **known == *name)
+                .ok_or_else(|| anyhow!("there is no snapshot named {name} in {shown}"))?
+        }
+        None => names
+            .pop()
+            .ok_or_else(|| anyhow!("there are no snapshots in {shown}"))?,
+    };
+
+    let path = dir.join(format!("{name}.json"));
+    let bytes = tokio::fs::read(&path)
+        .await
+        .with_context(|| format!("unable to read {}", path.display()))?;
+    let snapshot = serde_json::from_slice(&bytes)
+        .with_context(|| format!("corrupt snapshot {}", path.display()))?;
+    Ok((name, snapshot))
+}
+
+impl DdnsContext {
+    /// saves the records about to be changed, given as `(zone, zone id, record id)`,
+    /// before the first write of the session, later writes go through without one
+    ///
+    /// records that are about to be created have nothing to save, so they don't count as the first write
+    pub(crate) async fn snapshot_once(&self, records: &[(&Zone, &str, &str)]) -> Result<()> {
+        let mut taken = self.snapshot_taken.lock().await;
+        if *taken || records.is_empty() {
+            return Ok(());
+        }
+
+        let saved = futures::future::try_join_all(records.iter().map(
+            |&(zone, zone_id, record_id)| async move {
+                let mut record = self
+                    .cloudflare
+                    .get_dns_record::<SavedRecord>(zone.account(), zone_id, record_id)
+                    .await?;
+                record.zone_id = Box::from(zone_id);
+                record.account = Some(zone.account().fingerprint());
+                anyhow::Ok(record)
+            },
+        ))
+        .await?;
+
+        let snapshot = Snapshot {
+            taken: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |time| time.as_secs()),
+            records: saved,
+        };
+
+        let dir = self.state_dir.join(SNAPSHOT_DIR);
+        tokio::fs::create_dir_all(&dir).await?;
+        let bytes = serde_json::to_vec_pretty(&snapshot)?;
+        let mut count = 0_u64;
+        let path = loop {
+            let path = match count {
+                0 => dir.join(format!("{}.json", snapshot.taken)),
+                _ => dir.join(format!("{}-{count}.json", snapshot.taken)),
+            };
+            match tokio::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+                .await
+            {
+                Ok(mut file) => {
+                    tokio::io::AsyncWriteExt::write_all(&mut file, &bytes).await?;
+                    break path;
+                }
+                Err(err) if err.kind() == ErrorKind::AlreadyExists => count += 1,
+                Err(err) => return Err(err.into()),
+            }
+        };
+        dbg_println!(
+            "saved {} records to {}",
+            snapshot.records.len(),
+            path.display()
+        );
+
+        *taken = true;
+        Ok(())
+    }
+
+    /// puts every record of a snapshot back the way it was, the latest snapshot if none is named,
+    /// recreating the records that were deleted since
+    ///
+    /// a record that can't be restored doesn't stop the rest,
+    /// returns how many records made it back along with why the others didn't
+    pub(crate) async fn rollback(
+        &self,
+        cfg: &Config,
+        name: Option<&str>,
+    ) -> Result<(usize, Vec<anyhow::Error>)> {
+        let (name, snapshot) = read_snapshot(&self.state_dir.join(SNAPSHOT_DIR), name).await?;
+        dbg_println!("rolling back to the snapshot {name}");
+
+        let mut restored = 0;
+        let mut errors = vec![];
+        for record in &snapshot.records {
+            let context = || {
+                format!(
+                    "unable to restore the {} record {}",
+                    record.record_type, record.name
+                )
+            };
+
+            match self.restore(cfg, record).await.with_context(context) {
+                Ok(()) => restored += 1,
+                Err(err) => errors.push(err),
+            }
+        }
+
+        Ok((restored, errors))
+    }
+
+    async fn restore(&self, cfg: &Config, record: &SavedRecord) -> Result<()> {
+        let account = account_for(cfg, record)
+            .ok_or_else(|| anyhow!("no account in the config manages {}", record.name))?;
+
+        let data = record
+            .data
+            .as_ref()
+            .and_then(|data| serde_json::from_value::<SvcbData>(data.clone()).ok());
+        let body = record.body(data.as_ref());
+
+        let exists = match self
+            .cloudflare
+            .get_dns_record::<serde::de::IgnoredAny>(account, &record.zone_id, &record.id)
+            .await
+        {
+            Ok(_) => true,
+            Err(ApiError::NotFound(_)) => false,
+            Err(err) => return Err(err.into()),
+        };
+
+        match exists {
+            true => self
+                .cloudflare
+                .patch_dns_record(account, &record.zone_id, &record.id, &body)
+                .await
+                .map(drop),
+            false => self
+                .cloudflare
+                .create_dns_record(account, &record.zone_id, &body)
+                .await
+                .map(drop),
+        }?;
+
+        // so the restored address isn't mistaken for someone else's change
+        let ip = record
+            .content
+            .as_deref()
+            .and_then(|ip| ip.parse::<IpAddr>().ok());
+        if let Some(ip) = ip.filter(|ip| IpFamily::of(ip).record_type() == &*record.record_type) {
+            self.published
+                .set(Published::key(&record.name, &record.record_type), ip)
+                .await
+                .context("unable to save the published address")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::api_fields::ApiFields;
+
+    #[test]
+    fn orders_snapshots_by_time_then_count() {
+        let mut names = [
+            "1700000001",
+            "1700000000-10",
+            "1700000000",
+            "1700000000-2",
+            "1700000000-1",
+        ];
+        names.sort_by_key(|name| snapshot_order(name));
+        assert_eq!(
+            names,
+            [
+                "1700000000",
+                "1700000000-1",
+                "1700000000-2",
+                "1700000000-10",
+                "1700000001"
+            ]
+        );
+    }
+
+    fn saved(name: &str, account: Option<u64>) -> SavedRecord {
+        let mut record: SavedRecord = serde_json::from_value(serde_json::json!({
+            "id": "1",
+            "name": name,
+            "type": "A",
+            "content": "192.0.2.1",
+            "ttl": 1,
+        }))
+        .unwrap();
+        record.account = account;
+        record
+    }
+
+    #[test]
+    fn restores_through_the_account_it_was_read_with() {
+        let api: ApiFields = r#"
+            [account]
+            api-token = "8dY3nH-As0krmv83n3pm1l"
+
+            [[zone]]
+            record = "home.example.com"
+
+            [[zone]]
+            record = "office.example.com"
+            account = { api-token = "Xq7Lp2-Vb9nM4kT1wRzC" }
+            "#
+        .parse()
+        .unwrap();
+        let cfg = Config::builder(api).build();
+        let (home, office) = (cfg.zones()[0].account(), cfg.zones()[1].account());
+        let picked = |record: &SavedRecord| account_for(&cfg, record).map(Account::fingerprint);
+
+        // the record moved to another account in the config since the snapshot
+        let moved = saved("home.example.com", Some(office.fingerprint()));
+        assert_eq!(picked(&moved), Some(office.fingerprint()));
+
+        // older snapshots go by the name, and the top level account for everything else
+        assert_eq!(
+            picked(&saved("office.example.com", None)),
+            Some(office.fingerprint())
+        );
+        assert_eq!(
+            picked(&saved("_ddns.home.example.com", None)),
+            Some(home.fingerprint())
+        );
+
+        // an account that's gone from the config can't restore anything
+        assert_eq!(picked(&saved("home.example.com", Some(0))), None);
+    }
+
+    #[tokio::test]
+    async fn keeps_the_account_and_never_overwrites() {
+        let (api, _) = crate::simulate::fake_api(
+            r#"
+            [account]
+            api-token = "8dY3nH-As0krmv83n3pm1l"
+
+            [[zone]]
+            record = "home.example.com"
+            family = "v4"
+            "#,
+            |_| false,
+        )
+        .await
+        .unwrap();
+        let cfg = Config::builder(api).build();
+        let zone = &cfg.zones()[0];
+
+        // two sessions sharing a state directory, like two runs in the same second
+        let first = crate::tests::context(&cfg, "snapshot");
+        let second = DdnsContext::builder(cfg.clone())
+            .state_dir(&first.state_dir)
+            .build();
+
+        let record = &first.records(zone, IpFamily::V4).await.unwrap()[0];
+        let zone_id = first.zone_id(zone).await.unwrap();
+        for ctx in [&first, &second] {
+            ctx.snapshot_once(&[(zone, &zone_id, &record.dns.id)])
+                .await
+                .unwrap();
+        }
+
+        let dir = first.state_dir.join(SNAPSHOT_DIR);
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+        let (_, snapshot) = read_snapshot(&dir, None).await.unwrap();
+        assert_eq!(
+            snapshot.records[0].account,
+            Some(zone.account().fingerprint())
+        );
+    }
+}
@@ -0,0 +1,137 @@
+use crate::dbg_println;
+use anyhow::{Context, Result};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// where the daemon keeps its state, relative to its working directory
+pub const DEFAULT_STATE_DIR: &str = "./state";
+const PUBLISHED_FILE: &str = "published.json";
+/// records changed from outside that we refuse to overwrite, with the address they were changed to
+pub const HELD_FILE: &str = "held.json";
+/// the held records someone acknowledged, written by the `acknowledge` subcommand
+pub const ACKNOWLEDGED_FILE: &str = "acknowledged.json";
+/// the records we created for `[[records]]`, keyed by their id
+pub const CREATED_FILE: &str = "created.json";
+/// where the daemon writes its high availability role, for the `status` subcommand
+pub const ROLE_FILE: &str = "role.json";
+
+/// a map kept on disk in the state directory, so it survives restarts
+pub struct StateMap<V> {
+    path: PathBuf,
+    map: Mutex<BTreeMap<Box<str>, V>>,
+    write: tokio::sync::Mutex<()>,
+}
+
+/// the addresses this daemon last published, keyed by `record/type`
+pub type Published = StateMap<IpAddr>;
+
+impl Published {
+    pub fn key(record: &str, record_type: &str) -> Box<str> {
+        format!("{record}/{record_type}").into_boxed_str()
+    }
+
+    pub fn load(state_dir: &Path) -> Self {
+        Self::load_file(state_dir.join(PUBLISHED_FILE))
+    }
+}
+
+impl<V: Clone + PartialEq + Serialize + DeserializeOwned> StateMap<V> {
+    pub fn load_file(path: PathBuf) -> Self {
+        fn read<V: DeserializeOwned>(path: &Path) -> Result<BTreeMap<Box<str>, V>> {
+            match std::fs::read(path) {
+                Ok(bytes) => serde_json::from_slice(&bytes).context("corrupt state file"),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+                Err(err) => Err(err.into()),
+            }
+        }
+
+        let map = read(&path).unwrap_or_else(|err| {
+            dbg_println!("unable to load {}: {err:#}", path.display());
+            BTreeMap::new()
+        });
+
+        StateMap {
+            path,
+            map: Mutex::new(map),
+            write: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<V> {
+        self.map.lock().unwrap().get(key).cloned()
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.map.lock().unwrap().contains_key(key)
+    }
+
+    /// every entry, as of now
+    pub fn entries(&self) -> Vec<(Box<str>, V)> {
+        let map = self.map.lock().unwrap();
+        map.iter()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect()
+    }
+
+    pub async fn set(&self, key: Box<str>, value: V) -> Result<()> {
+        self.modify(|map| {
+            if map.get(&key) == Some(&value) {
+                return false;
+            }
+            map.insert(key, value);
+            true
+        })
+        .await
+    }
+
+    /// forgets the entry, like the address of a record we no longer publish anything to
+    pub async fn remove(&self, key: &str) -> Result<()> {
+        self.modify(|map| map.remove(key).is_some()).await
+    }
+
+    /// applies the change and writes the state file, unless `change` reports that nothing changed
+    async fn modify(&self, change: impl FnOnce(&mut BTreeMap<Box<str>, V>) -> bool) -> Result<()> {
+        // hold the write lock across the whole write, so writers can't race each other's rename
+        let _write = self.write.lock().await;
+        let json = {
+            let mut map = self.map.lock().unwrap();
+            if !change(&mut map) {
+                return Ok(());
+            }
+            serde_json::to_vec_pretty(&*map)?
+        };
+
+        write_file(&self.path, json).await
+    }
+}
+
+/// writes next to the file and renames it in place, so readers never see half of it
+async fn write_file(path: &Path, bytes: Vec<u8>) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    tokio::fs::write(&tmp, bytes).await?;
+    tokio::fs::rename(&tmp, path).await?;
+    Ok(())
+}
+
+/// replaces a whole state file with the value
+pub async fn write_json(path: &Path, value: &impl Serialize) -> Result<()> {
+    write_file(path, serde_json::to_vec_pretty(value)?).await
+}
+
+/// lets the daemon overwrite every record it currently holds back,
+/// the acknowledgement only covers the outside changes seen so far
+pub fn acknowledge(state_dir: &Path) -> io::Result<()> {
+    match std::fs::copy(state_dir.join(HELD_FILE), state_dir.join(ACKNOWLEDGED_FILE)) {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        res => res.map(drop),
+    }
+}
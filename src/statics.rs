@@ -0,0 +1,456 @@
+use crate::cloudflare::{DnsRecord, RecordFilter};
+use crate::config::api_fields::{StaticRecord, Zone};
+use crate::config::Config;
+use crate::{dbg_println, record_context, Change, ChangeKind, DdnsContext};
+use anyhow::{anyhow, Result};
+use futures::future;
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use std::sync::Arc;
+
+/// a record we created for `[[records]]`, it's ours to delete once it isn't declared anymore
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub(crate) struct CreatedRecord {
+    zone_id: Box<str>,
+    name: Box<str>,
+    #[serde(rename = "type")]
+    record_type: Box<str>,
+    /// the fingerprint of the account it was created through,
+    /// missing from records created before it was kept, those go through the top level account
+    #[serde(default)]
+    account: Option<u64>,
+}
+
+/// what the declared records need, along with the ones that already match
+#[derive(Default)]
+pub(crate) struct StaticPlan<'a> {
+    pub(crate) changes: Vec<Change<'a>>,
+    pub(crate) unchanged: usize,
+    pub(crate) errors: Vec<anyhow::Error>,
+}
+
+/// compares contents the way dns does, ignoring the case and the final dot of names,
+/// and the quotes cloudflare may or may not keep around TXT values
+fn same_content(record_type: &str, a: &str, b: &str) -> bool {
+    match record_type {
+        "A" | "AAAA" => match (a.parse::<IpAddr>(), b.parse::<IpAddr>()) {
+            (Ok(a), Ok(b)) => a == b,
+            _ => a == b,
+        },
+        "TXT" => {
+            let unquote = |txt: &str| {
+                txt.strip_prefix('"')
+                    .and_then(|txt| txt.strip_suffix('"'))
+                    .unwrap_or(txt)
+                    .to_owned()
+            };
+            unquote(a) == unquote(b)
+        }
+        _ => a
+            .trim_end_matches('.')
+            .eq_ignore_ascii_case(b.trim_end_matches('.')),
+    }
+}
+
+/// lists every field of the record that doesn't match its declaration
+fn drift(record: &StaticRecord, dns: &DnsRecord) -> Vec<String> {
+    let zone = record.zone();
+    let mut changes = vec![];
+
+    if !same_content(record.record_type(), &dns.content, record.content()) {
+        changes.push(format!(
+            "content {:?} -> {:?}",
+            dns.content,
+            record.content()
+        ))
+    }
+
+    if StaticRecord::proxiable(record.record_type()) && dns.proxied != zone.proxied() {
+        changes.push(format!("proxied {} -> {}", dns.proxied, zone.proxied()))
+    }
+
+    if let Some(ttl) = zone.ttl() {
+        if dns.ttl != ttl {
+            changes.push(format!("ttl {} -> {ttl}", dns.ttl))
+        }
+    }
+
+    if let Some(priority) = record.priority() {
+        if dns.priority != Some(priority) {
+            changes.push(format!("priority {:?} -> {priority}", dns.priority))
+        }
+    }
+
+    if let Some(comment) = zone.comment() {
+        if dns.comment.as_deref() != Some(comment) {
+            changes.push(format!("comment {:?} -> {comment:?}", dns.comment))
+        }
+    }
+
+    if let Some(tags) = zone.tags() {
+        let mut current = dns.tags.clone();
+        current.sort_unstable();
+        current.dedup();
+        if *current != *tags {
+            changes.push(format!("tags {current:?} -> {tags:?}"))
+        }
+    }
+
+    changes
+}
+
+/// pairs every declared record of a group with the existing record it should become, `None` creates it,
+/// the existing records nothing was paired with are left in `existing`
+///
+/// a record already holding its declared value stays as it is, a changed value is ours to fix when we created the record,
+/// when the type can only hold one record anyway, or when it's the one value that changed and a single record is left,
+/// like a value edited from the dashboard
+fn pair_up<'r>(
+    group: Vec<&'r StaticRecord>,
+    existing: &mut Vec<DnsRecord>,
+    created: impl Fn(&str) -> bool,
+) -> Vec<(&'r StaticRecord, Option<DnsRecord>)> {
+    let mut matched = vec![];
+    let mut unmatched = vec![];
+    for record in group {
+        let same = existing
+            .iter()
+            .position(|dns| same_content(record.record_type(), &dns.content, record.content()));
+        match same {
+            Some(pos) => matched.push((record, Some(existing.swap_remove(pos)))),
+            None => unmatched.push(record),
+        }
+    }
+
+    let edited = unmatched.len() == 1 && existing.len() == 1;
+    for record in unmatched {
+        let ours = existing
+            .iter()
+            .position(|dns| created(&dns.id))
+            .or_else(|| {
+                let reuse = edited || record.record_type() == "CNAME";
+                (reuse && !existing.is_empty()).then_some(0)
+            });
+        matched.push((record, ours.map(|pos| existing.swap_remove(pos))));
+    }
+    matched
+}
+
+impl DdnsContext {
+    async fn get_static_records(&self, record: &StaticRecord) -> Result<Vec<DnsRecord>> {
+        let zone = record.zone();
+        let filter = RecordFilter {
+            record_type: Some(record.record_type()),
+            name: Some(record.name()),
+        };
+
+        let records = self
+            .cloudflare
+            .list_dns_records::<DnsRecord>(zone.account(), &self.zone_id(zone).await?, filter)
+            .await?;
+
+        // the filter is only a hint, don't trust it with records we might delete
+        Ok(records
+            .into_iter()
+            .filter(|dns| &*dns.name == record.name() && *dns.record_type == *record.record_type())
+            .collect())
+    }
+
+    /// the records we created whose name and type aren't declared anymore,
+    /// each with a zone to delete it through, since nothing in the config points at them
+    pub(crate) fn leftover_records(&self, cfg: &Config) -> Vec<(Zone, Box<str>, CreatedRecord)> {
+        self.created
+            .entries()
+            .into_iter()
+            .filter(|(_, created)| {
+                !cfg.records().iter().any(|record| {
+                    record.name() == &*created.name && record.record_type() == &*created.record_type
+                })
+            })
+            .filter_map(|(id, created)| {
+                // once its account is gone from the config there's nobody left to delete it as
+                let account = match created.account {
+                    Some(fingerprint) => cfg.account_by_fingerprint(fingerprint)?,
+                    None => cfg.account()?,
+                };
+                let account = Arc::clone(account);
+                let zone = Zone::bare(
+                    Some(created.zone_id.clone()),
+                    None,
+                    created.name.clone(),
+                    account,
+                );
+                Some((zone, id, created))
+            })
+            .collect()
+    }
+
+    /// matches the declared records with the ones cloudflare has, and works out what has to change,
+    /// only records we created ourselves are ever deleted
+    pub(crate) async fn plan_static_records<'a>(
+        &self,
+        records: &'a [StaticRecord],
+        leftovers: &'a [(Zone, Box<str>, CreatedRecord)],
+    ) -> StaticPlan<'a> {
+        let mut plan = StaticPlan::default();
+
+        // every record sharing a name and type is matched up together, like the TXT values of a domain
+        let mut groups = Vec::<Vec<&StaticRecord>>::new();
+        for record in records {
+            match groups.iter_mut().find(|group| {
+                group[0].name() == record.name() && group[0].record_type() == record.record_type()
+            }) {
+                Some(group) => group.push(record),
+                None => groups.push(vec![record]),
+            }
+        }
+
+        let fetched =
+            future::join_all(groups.iter().map(|group| self.get_static_records(group[0]))).await;
+
+        for (group, existing) in groups.into_iter().zip(fetched) {
+            let first = group[0];
+            let mut existing = match existing {
+                Ok(existing) => existing,
+                Err(err) => {
+                    plan.errors
+                        .push(record_context(err, first.record_type(), first.name()));
+                    continue;
+                }
+            };
+
+            let matched = pair_up(group, &mut existing, |id| self.created.contains(id));
+            for (record, dns) in matched {
+                let (update, unmarked) = match dns {
+                    Some(dns) => {
+                        let changes = drift(record, &dns);
+                        if changes.is_empty() {
+                            plan.unchanged += 1;
+                            continue;
+                        }
+                        let drift = changes.join(", ");
+                        dbg_println!("updating {}: {drift}", record.name());
+                        (Some((dns.id, drift)), dns.comment.is_none())
+                    }
+                    None => (None, true),
+                };
+
+                plan.changes.push(Change {
+                    zone: record.zone(),
+                    kind: ChangeKind::Static {
+                        record_type: Box::from(record.record_type()),
+                        content: Box::from(record.content()),
+                        priority: record.priority(),
+                        update,
+                        unmarked,
+                        created: None,
+                    },
+                });
+            }
+
+            // whatever we created under this name that's left over isn't declared anymore
+            for dns in existing {
+                if self.created.contains(&dns.id) {
+                    plan.changes.push(Change {
+                        zone: first.zone(),
+                        kind: ChangeKind::Forget {
+                            record_type: dns.record_type,
+                            id: dns.id,
+                        },
+                    });
+                }
+            }
+        }
+
+        for (zone, id, created) in leftovers {
+            plan.changes.push(Change {
+                zone,
+                kind: ChangeKind::Forget {
+                    record_type: created.record_type.clone(),
+                    id: id.clone(),
+                },
+            });
+        }
+
+        plan
+    }
+
+    /// keeps track of the records we created, and forgets the ones we deleted
+    pub(crate) async fn finish_static(&self, change: &Change<'_>) -> Result<()> {
+        let zone = change.zone;
+        match &change.kind {
+            ChangeKind::Static {
+                record_type,
+                update: Some((_, drift)),
+                ..
+            } => {
+                self.user_messages
+                    .warning(format!(
+                        "the {record_type} record {} drifted from the config, corrected {drift}",
+                        zone.record()
+                    ))
+                    .await;
+                Ok(())
+            }
+            ChangeKind::Static {
+                record_type,
+                created,
+                ..
+            } => {
+                dbg_println!("created the {record_type} record {}", zone.record());
+                let id = created
+                    .clone()
+                    .ok_or_else(|| anyhow!("cloudflare didn't say which id the record got"))?;
+                let created = CreatedRecord {
+                    zone_id: Box::from(&*self.zone_id(zone).await?),
+                    name: Box::from(zone.record()),
+                    record_type: record_type.clone(),
+                    account: Some(zone.account().fingerprint()),
+                };
+                self.created.set(id, created).await
+            }
+            ChangeKind::Forget { record_type, id } => {
+                dbg_println!(
+                    "deleted the {record_type} record {}, it isn't declared anymore",
+                    zone.record()
+                );
+                self.created.remove(id).await
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::api_fields::ApiFields;
+
+    fn declared(records: &str) -> ApiFields {
+        format!(
+            r#"
+            [account]
+            api-token = "8dY3nH-As0krmv83n3pm1l"
+            {records}
+            "#
+        )
+        .parse()
+        .unwrap()
+    }
+
+    fn existing(id: &str, record_type: &str, content: &str) -> DnsRecord {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "name": "example.com",
+            "type": record_type,
+            "content": content,
+            "ttl": 1,
+        }))
+        .unwrap()
+    }
+
+    /// the ids of the records every declared record was paired with, and the ones left over
+    fn paired(
+        api: &ApiFields,
+        mut existing: Vec<DnsRecord>,
+        created: &[&str],
+    ) -> (Vec<Option<Box<str>>>, Vec<Box<str>>) {
+        let group = api.records.iter().collect::<Vec<_>>();
+        let pairs = pair_up(group, &mut existing, |id| created.contains(&id));
+        let mut pairs = pairs
+            .into_iter()
+            .map(|(record, dns)| (record.content(), dns.map(|dns| dns.id)))
+            .collect::<Vec<_>>();
+        pairs.sort_unstable();
+        let mut left = existing.into_iter().map(|dns| dns.id).collect::<Vec<_>>();
+        left.sort_unstable();
+        (pairs.into_iter().map(|(_, id)| id).collect(), left)
+    }
+
+    const TXT: &str = r#"
+        [[records]]
+        name = "example.com"
+        type = "TXT"
+        content = "v=spf1 include:_spf.example.net -all"
+    "#;
+
+    #[test]
+    fn addresses_compare_by_value() {
+        assert!(same_content("AAAA", "2001:db8::1", "2001:0db8:0:0::1"));
+        assert!(same_content("A", "192.0.2.1", "192.0.2.1"));
+        assert!(!same_content("A", "192.0.2.1", "192.0.2.2"));
+        assert!(!same_content("A", "not an ip", "192.0.2.1"));
+    }
+
+    #[test]
+    fn txt_values_ignore_the_quotes() {
+        assert!(same_content("TXT", "\"v=spf1 -all\"", "v=spf1 -all"));
+        assert!(same_content("TXT", "v=spf1 -all", "\"v=spf1 -all\""));
+        assert!(!same_content("TXT", "V=SPF1 -all", "v=spf1 -all"));
+    }
+
+    #[test]
+    fn names_ignore_the_case_and_the_final_dot() {
+        assert!(same_content(
+            "CNAME",
+            "Target.Example.com.",
+            "target.example.com"
+        ));
+        assert!(same_content("MX", "mail.example.com", "MAIL.example.com."));
+        assert!(!same_content("CNAME", "a.example.com", "b.example.com"));
+    }
+
+    #[test]
+    fn takes_over_a_value_edited_in_the_dashboard() {
+        let api = declared(TXT);
+        let edited = existing("1", "TXT", "\"v=spf1 ~all\"");
+        assert_eq!(
+            paired(&api, vec![edited], &[]),
+            (vec![Some("1".into())], vec![])
+        );
+    }
+
+    #[test]
+    fn leaves_the_other_values_alone() {
+        let api = declared(TXT);
+        let records = vec![
+            existing("1", "TXT", "\"google-site-verification=abc\""),
+            existing("2", "TXT", "\"v=spf1 ~all\""),
+        ];
+        assert_eq!(
+            paired(&api, records, &[]),
+            (vec![None], vec!["1".into(), "2".into()])
+        );
+
+        // unless we created one of them
+        let records = vec![
+            existing("1", "TXT", "\"google-site-verification=abc\""),
+            existing("2", "TXT", "\"v=spf1 ~all\""),
+        ];
+        assert_eq!(
+            paired(&api, records, &["2"]),
+            (vec![Some("2".into())], vec!["1".into()])
+        );
+    }
+
+    #[test]
+    fn matches_the_values_that_didnt_change_first() {
+        let api = declared(&format!(
+            r#"
+            {TXT}
+            [[records]]
+            name = "example.com"
+            type = "TXT"
+            content = "google-site-verification=abc"
+            "#
+        ));
+        let records = vec![
+            existing("1", "TXT", "\"v=spf1 ~all\""),
+            existing("2", "TXT", "\"google-site-verification=abc\""),
+        ];
+        assert_eq!(
+            paired(&api, records, &[]),
+            (vec![Some("2".into()), Some("1".into())], vec![])
+        );
+    }
+}
@@ -0,0 +1,230 @@
+use crate::cloudflare::{RecordFilter, SvcbData, SvcbRecord};
+use crate::config::api_fields::Zone;
+use crate::config::family::IpFamily;
+use crate::{Change, ChangeKind, DdnsContext};
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+
+const SVCB_TYPES: [&str; 2] = ["HTTPS", "SVCB"];
+
+const fn hint_key(family: IpFamily) -> &'static str {
+    match family {
+        IpFamily::V4 => "ipv4hint",
+        IpFamily::V6 => "ipv6hint",
+    }
+}
+
+/// splits SvcParams on whitespace, keeping quoted values together
+fn split_params(value: &str) -> Vec<&str> {
+    let mut params = vec![];
+    let mut start = None;
+    let mut quoted = false;
+    let mut escaped = false;
+
+    for (i, c) in value.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => quoted = !quoted,
+            c if c.is_ascii_whitespace() && !quoted => {
+                if let Some(start) = start.take() {
+                    params.push(&value[start..i]);
+                }
+                continue;
+            }
+            _ => {}
+        }
+        start.get_or_insert(i);
+    }
+
+    if let Some(start) = start {
+        params.push(&value[start..]);
+    }
+    params
+}
+
+/// rewrites the address hints in the SvcParams, leaving every other param as it is,
+/// only hints the record already has are touched, and `None` is returned if none of them changed
+fn rewrite_hints(value: &str, hints: &BTreeMap<IpFamily, IpAddr>) -> Option<String> {
+    let params = split_params(value);
+    let rewritten = params
+        .iter()
+        .map(|&param| {
+            let (key, current) = param.split_once('=').unwrap_or((param, ""));
+            match hints.iter().find(|(&family, _)| hint_key(family) == key) {
+                // the same address written without quotes is already up to date
+                Some((_, ip)) if current.trim_matches('"') != ip.to_string() => {
+                    format!("{key}=\"{ip}\"")
+                }
+                _ => param.to_owned(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    (rewritten != params.join(" ")).then_some(rewritten)
+}
+
+impl DdnsContext {
+    pub(crate) async fn get_svcb_records(&self, zone: &Zone) -> Result<Vec<SvcbRecord>> {
+        let zone_id = self.zone_id(zone).await?;
+        let lists = SVCB_TYPES.map(|record_type| {
+            let filter = RecordFilter {
+                record_type: Some(record_type),
+                name: Some(zone.record()),
+            };
+            self.cloudflare
+                .list_dns_records::<SvcbRecord>(zone.account(), &zone_id, filter)
+        });
+
+        let records = futures::future::try_join_all(lists).await?;
+        Ok(records
+            .into_iter()
+            .flatten()
+            .filter(|record| &*record.name == zone.record())
+            .collect())
+    }
+
+    /// works out the HTTPS and SVCB records whose address hints are out of date,
+    /// priority, target and the other params are kept as they are
+    ///
+    /// the hints follow the addresses the zone's own records are pointed at,
+    /// so a lan host or a failover candidate shows up in them too
+    pub(crate) fn plan_hints<'a>(
+        zone: &'a Zone,
+        records: Vec<SvcbRecord>,
+        targets: &[(&Zone, IpFamily, IpAddr)],
+    ) -> Vec<Change<'a>> {
+        let hints = targets
+            .iter()
+            .filter(|&&(other, ..)| std::ptr::eq(other, zone))
+            .map(|&(_, family, ip)| (family, ip))
+            .collect::<BTreeMap<_, _>>();
+
+        records
+            .into_iter()
+            .filter_map(|record| {
+                let value = rewrite_hints(&record.data.value, &hints)?;
+                crate::dbg_println!(
+                    "updating the {} record {}: {} -> {value}",
+                    record.record_type,
+                    zone.record(),
+                    record.data.value
+                );
+                Some(Change {
+                    zone,
+                    kind: ChangeKind::Hints {
+                        id: record.id,
+                        record_type: record.record_type,
+                        data: SvcbData {
+                            value: value.into_boxed_str(),
+                            ..record.data
+                        },
+                    },
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::api_fields::ApiFields;
+
+    fn hints(ips: &[&str]) -> BTreeMap<IpFamily, IpAddr> {
+        ips.iter()
+            .map(|ip| ip.parse().unwrap())
+            .map(|ip| (IpFamily::of(&ip), ip))
+            .collect()
+    }
+
+    #[test]
+    fn splits_on_whitespace_outside_quotes() {
+        assert_eq!(
+            split_params(r#"alpn="h3,h2"  ipv4hint=192.0.2.1 mandatory="alpn, ipv4hint""#),
+            [
+                "alpn=\"h3,h2\"",
+                "ipv4hint=192.0.2.1",
+                "mandatory=\"alpn, ipv4hint\""
+            ]
+        );
+        assert_eq!(split_params(r#"a="x\" y" b"#), [r#"a="x\" y""#, "b"]);
+        assert!(split_params("   ").is_empty());
+    }
+
+    #[test]
+    fn rewrites_only_the_hints_it_has() {
+        let value = r#"alpn="h3,h2" ipv4hint="192.0.2.1" ech="AEn+DQ==""#;
+        assert_eq!(
+            rewrite_hints(value, &hints(&["203.0.113.7", "2001:db8::7"])).as_deref(),
+            Some(r#"alpn="h3,h2" ipv4hint="203.0.113.7" ech="AEn+DQ==""#)
+        );
+    }
+
+    #[test]
+    fn leaves_up_to_date_hints_alone() {
+        let ips = hints(&["203.0.113.7", "2001:db8::7"]);
+        assert_eq!(
+            rewrite_hints(r#"alpn=h2 ipv4hint="203.0.113.7""#, &ips),
+            None
+        );
+        assert_eq!(rewrite_hints("ipv6hint=2001:db8::7", &ips), None);
+        assert_eq!(rewrite_hints("alpn=h2", &ips), None);
+    }
+
+    #[test]
+    fn ignores_families_without_an_address() {
+        let value = r#"ipv4hint="192.0.2.1" ipv6hint="2001:db8::1""#;
+        assert_eq!(
+            rewrite_hints(value, &hints(&["2001:db8::7"])).as_deref(),
+            Some(r#"ipv4hint="192.0.2.1" ipv6hint="2001:db8::7""#)
+        );
+    }
+
+    #[test]
+    fn follows_the_targets_of_its_own_zone() {
+        let api: ApiFields = r#"
+            [account]
+            api-token = "8dY3nH-As0krmv83n3pm1l"
+
+            [[zone]]
+            record = "home.example.com"
+            svcb-hints = true
+
+            [[zone]]
+            record = "office.example.com"
+        "#
+        .parse()
+        .unwrap();
+        let (home, office) = (&api.zones[0], &api.zones[1]);
+
+        let record = SvcbRecord {
+            id: "1".into(),
+            name: "home.example.com".into(),
+            record_type: "HTTPS".into(),
+            data: SvcbData {
+                priority: 1,
+                target: ".".into(),
+                value: r#"ipv4hint="192.0.2.1" ipv6hint="2001:db8::1""#.into(),
+            },
+        };
+        // like a lan host address, which only the home zone points at
+        let targets = [
+            (office, IpFamily::V4, ip_macro::ip!("203.0.113.7")),
+            (home, IpFamily::V6, ip_macro::ip!("2001:db8::42")),
+        ];
+
+        match &*DdnsContext::plan_hints(home, vec![record], &targets) {
+            [Change {
+                kind: ChangeKind::Hints { data, .. },
+                ..
+            }] => assert_eq!(
+                &*data.value,
+                r#"ipv4hint="192.0.2.1" ipv6hint="2001:db8::42""#
+            ),
+            changes => panic!("expected one hint change, got {changes:?}"),
+        }
+    }
+}
@@ -1,6 +1,5 @@
 use std::convert::Infallible;
-use std::fmt::{Display, Formatter, Write};
-use std::net::{self, Ipv4Addr};
+use std::net::{self, IpAddr, Ipv4Addr, Ipv6Addr};
 use std::num::NonZero;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
@@ -81,43 +80,19 @@ pub fn new_skip_interval_at(start: Instant, period: Duration) -> Interval {
     interval
 }
 
-pub struct EscapeJson<'a>(&'a str);
-
-impl<'a> Display for EscapeJson<'a> {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        let mut utf16_buf = [0u16; 2];
-        for c in self.0.chars() {
-            match c {
-                '\x08' => f.write_str("\\b"),
-                '\x0c' => f.write_str("\\f"),
-                '\n' => f.write_str("\\n"),
-                '\r' => f.write_str("\\r"),
-                '\t' => f.write_str("\\t"),
-                '"' => f.write_str("\\\""),
-                '\\' => f.write_str("\\"),
-                ' ' => f.write_char(' '),
-                c if c.is_ascii_graphic() => f.write_char(c),
-                c => {
-                    let encoded = c.encode_utf16(&mut utf16_buf);
-                    for utf16 in encoded {
-                        write!(f, "\\u{:04X}", utf16)?;
-                    }
-                    Ok(())
-                }
-            }?
+/// checks if `domain` is `zone` itself or one of its subdomains,
+/// both are expected to already be in their ascii form
+pub fn is_subdomain(domain: &str, zone: &str) -> bool {
+    let domain = domain.trim_end_matches('.');
+    let zone = zone.trim_end_matches('.');
+
+    match domain.len().checked_sub(zone.len()) {
+        Some(0) => domain.eq_ignore_ascii_case(zone),
+        Some(split) => {
+            domain.as_bytes()[split - 1] == b'.'
+                && domain.as_bytes()[split..].eq_ignore_ascii_case(zone.as_bytes())
         }
-
-        Ok(())
-    }
-}
-
-pub trait EscapeExt {
-    fn escape_json(&self) -> EscapeJson<'_>;
-}
-
-impl EscapeExt for str {
-    fn escape_json(&self) -> EscapeJson<'_> {
-        EscapeJson(self)
+        None => false,
     }
 }
 
@@ -135,15 +110,25 @@ pub trait AddrParseExt: Sized {
     fn parse_ascii_bytes(b: &[u8]) -> Result<Self, AddrParseError>;
 }
 
-impl AddrParseExt for Ipv4Addr {
-    fn parse_ascii_bytes(b: &[u8]) -> Result<Self, AddrParseError> {
-        if b.len() > b"xxx.xxx.xxx.xxx".len() {
-            return Err(AddrParseError::TooLong);
+macro_rules! impl_addr_parse {
+    ($($ty: ty => $longest: literal),* $(,)?) => {$(
+        impl AddrParseExt for $ty {
+            fn parse_ascii_bytes(b: &[u8]) -> Result<Self, AddrParseError> {
+                if b.len() > $longest.len() {
+                    return Err(AddrParseError::TooLong);
+                }
+
+                b.is_ascii()
+                    .then(|| unsafe { std::str::from_utf8_unchecked(b) })
+                    .ok_or(AddrParseError::InvalidEncoding)
+                    .and_then(|s| <$ty>::from_str(s).map_err(Into::into))
+            }
         }
+    )*};
+}
 
-        b.is_ascii()
-            .then(|| unsafe { std::str::from_utf8_unchecked(b) })
-            .ok_or(AddrParseError::InvalidEncoding)
-            .and_then(|s| Ipv4Addr::from_str(s).map_err(Into::into))
-    }
+impl_addr_parse! {
+    Ipv4Addr => b"xxx.xxx.xxx.xxx",
+    Ipv6Addr => b"xxxx:xxxx:xxxx:xxxx:xxxx:xxxx:xxx.xxx.xxx.xxx",
+    IpAddr   => b"xxxx:xxxx:xxxx:xxxx:xxxx:xxxx:xxx.xxx.xxx.xxx",
 }